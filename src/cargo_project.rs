@@ -1,22 +1,36 @@
 use std::{
-    collections::{HashMap, HashSet},
+    cell::RefCell,
+    collections::HashSet,
     fs::{self, File},
     io::{Read, Write},
     path::Path,
+    rc::Rc,
 };
 
-use crate::TomlInMemory;
+use crate::{TomlEdit, TomlInMemory};
 use cargo::{
     core::{
         compiler::{BuildConfig, CompileMode},
+        resolver::{CliFeatures, ResolveBehavior},
         Shell, Verbosity, Workspace,
     },
-    ops::{CompileFilter, CompileOptions},
+    ops::{CompileFilter, CompileOptions, Packages},
     Config,
 };
-use cargo_metadata::Metadata;
-
-use crate::{create_dependencies::CrateDependencies, subcommands::analyze::AnalyzeCommand};
+use cargo_metadata::{semver::VersionReq, CargoOpt, Metadata, Package, PackageId};
+use cargo_toml::{DependencyDetail, DepsSet};
+
+use crate::{
+    create_dependencies::{CrateDependencies, DependencyKey},
+    dependency_kind::DependencyKind,
+    feature_graph::{referenced_dependency, FeatureGraph},
+    feature_powerset::feature_powerset,
+    own_features::analyze_own_features,
+    platform::CompilePlatforms,
+    report::OwnFeatures,
+    strategy::{Search, Strategy},
+    subcommands::analyze::AnalyzeCommand,
+};
 
 /// In-memory toml file.
 pub struct CargoProject {
@@ -26,8 +40,12 @@ pub struct CargoProject {
     directory: Box<Path>,
     /// The absolute path of the toml file.
     toml_path: Box<Path>,
-    /// The in memory loaded toml definition that can be edited and serialized.
+    /// The parsed toml definition, used to read the dependencies and features.
     in_memory_toml: TomlInMemory,
+    /// The editable toml document the permutations are applied to, keeping everything else as is.
+    editable_toml: TomlEdit,
+    /// The platforms the project is compiled for.
+    platforms: CompilePlatforms,
     /// Configurations.
     config: AnalyzeCommand,
 }
@@ -47,14 +65,19 @@ impl CargoProject {
         log::debug!("Parsing toml definition ...");
 
         let in_memory_toml = TomlInMemory::new(toml_contents.clone())?;
+        let editable_toml = TomlEdit::new(toml_contents.clone())?;
 
         log::debug!("Successfully parsed the toml file.");
 
+        let platforms = CompilePlatforms::new(&config.build_target)?;
+
         Ok(CargoProject {
             original: toml_contents,
             directory: Box::from(directory),
             toml_path: toml_path.into_boxed_path(),
             in_memory_toml,
+            editable_toml,
+            platforms,
             config,
         })
     }
@@ -64,20 +87,84 @@ impl CargoProject {
         &self.config
     }
 
-    /// Returns a list of absolute path names to the workspace members of this toml file.
-    /// No paths are returned if this is not a workspace toml file.
-    pub fn workspace_members(&self) -> Vec<Box<Path>> {
-        if let Some(workspace) = &self.in_memory_toml.workspace {
-            let mut members = Vec::new();
-            for member in &workspace.members {
-                members.push(Box::from(self.directory.join(&member)));
+    /// Returns if this toml file defines a `[workspace]`.
+    pub fn is_workspace(&self) -> bool {
+        self.in_memory_toml.workspace.is_some()
+    }
+
+    /// Returns the package names and absolute directories of the workspace members of this toml file.
+    /// Members are discovered by cargo, thus globs are expanded and `exclude` is honored.
+    /// The package of a non-virtual workspace root is a member as well.
+    /// No members are returned if this is not a workspace toml file.
+    pub fn workspace_members(&self) -> anyhow::Result<Vec<(String, Box<Path>)>> {
+        self.discover_workspace_members(false)
+    }
+
+    /// Returns the workspace members to analyze.
+    /// Those are limited to `default-members`, the `--package` and the `--exclude` selection if configured.
+    pub fn selected_workspace_members(&self) -> anyhow::Result<Vec<(String, Box<Path>)>> {
+        let mut members = self.discover_workspace_members(self.config.default_members)?;
+
+        for package in &self.config.packages {
+            if !members
+                .iter()
+                .any(|(member_name, _)| member_name == package)
+            {
+                return Err(anyhow::anyhow!(
+                    "Package '{package}' is not a member of the workspace."
+                ));
             }
-            members
+        }
+
+        members.retain(|(member_name, _)| {
+            (self.config.packages.is_empty() || self.config.packages.contains(member_name))
+                && !self.config.exclude.contains(member_name)
+        });
+
+        Ok(members)
+    }
+
+    fn discover_workspace_members(
+        &self,
+        default_members_only: bool,
+    ) -> anyhow::Result<Vec<(String, Box<Path>)>> {
+        if self.in_memory_toml.workspace.is_none() {
+            return Ok(vec![]);
+        }
+
+        let config = Config::default()?;
+        let workspace = Workspace::new(&self.toml_path(), &config)?;
+
+        let members: Vec<_> = if default_members_only {
+            workspace.default_members().collect()
         } else {
-            vec![]
+            workspace.members().collect()
+        };
+
+        Ok(members
+            .into_iter()
+            .map(|package| (package.name().to_string(), Box::from(package.root())))
+            .collect())
+    }
+
+    /// Returns the name of the report root, the crate name or 'Workspace' for a virtual workspace.
+    pub fn root_name(&self) -> String {
+        match &self.in_memory_toml.package {
+            Some(package) => package.name.clone(),
+            None => "Workspace".to_string(),
         }
     }
 
+    /// Returns how the removals of the dependencies build on each other.
+    pub fn strategy(&self) -> Strategy {
+        self.config.strategy
+    }
+
+    /// Returns how the features of a dependency are searched for the ones that can be removed.
+    pub fn search(&self) -> Search {
+        self.config.search
+    }
+
     /// Returns the crate name in the toml file.
     pub fn crate_name(&self) -> String {
         self.in_memory_toml
@@ -87,6 +174,16 @@ impl CargoProject {
             .unwrap_or_default()
     }
 
+    /// Returns if dependencies declared for the given `target.'...'` platform are part of the build.
+    /// Host-side dependencies, like build-dependencies, are matched against the host platform as cargo does.
+    pub fn is_target_compiled(&self, target: &str, host: bool) -> bool {
+        if host {
+            self.platforms.matches_host(target)
+        } else {
+            self.platforms.matches(target)
+        }
+    }
+
     /// Returns the absolute path of the toml file.
     pub fn report_path(&self) -> Box<Path> {
         let report_dir = self
@@ -108,11 +205,11 @@ impl CargoProject {
         self.directory.clone()
     }
 
-    /// Flushes the changes made to the in memory toml to the toml file on disk.
+    /// Flushes the changes made to the editable toml to the toml file on disk.
     pub fn flush(&self) -> anyhow::Result<()> {
-        let toml_contents = self.in_memory_toml.serialize()?;
+        let toml_contents = self.editable_toml.serialize()?;
 
-        fs::write(&self.toml_path(), toml_contents.as_bytes())?;
+        fs::write(self.toml_path(), toml_contents.as_bytes())?;
 
         Ok(())
     }
@@ -120,25 +217,87 @@ impl CargoProject {
     /// Replaces the dependency features with the given features.
     pub fn replace_dependency_features(
         &mut self,
-        dependency_name: &String,
+        dependency: &DependencyKey,
         new_features: Vec<String>,
     ) -> anyhow::Result<()> {
-        self.in_memory_toml
-            .replace_dependency_feature(dependency_name, new_features)
+        self.editable_toml
+            .replace_dependency_features(dependency, new_features)
     }
 
-    /// Resets the dependencies of this in memory toml definition.
+    /// Resets the dependencies of the editable toml document.
     /// This does not update any files.
     pub fn reset_dependencies(&mut self) -> anyhow::Result<()> {
-        self.in_memory_toml.reset_dependencies()
+        self.editable_toml.reset()
+    }
+
+    /// Keeps the current dependency features, later resets return to those.
+    /// This does not update any files.
+    pub fn keep_dependencies(&mut self) {
+        self.editable_toml.keep_changes()
     }
 
-    /// Tries to compile the project of the this toml file.    
-    pub fn try_compile(&self) -> anyhow::Result<()> {
+    /// Resets the dependencies of the editable toml document to the original ones, discarding the kept ones.
+    /// This does not update any files.
+    pub fn reset_original_dependencies(&mut self) {
+        self.editable_toml.reset_original()
+    }
+
+    /// Returns the own feature combinations every removal is verified against.
+    /// Without `--feature-powerset` only the configured features are compiled, represented by the empty combination.
+    pub fn feature_combinations(&self) -> Vec<Vec<String>> {
+        if !self.config.feature_powerset || self.config.all_features {
+            return vec![vec![]];
+        }
+
+        let features = self
+            .in_memory_toml
+            .features
+            .keys()
+            .filter(|feature| feature.as_str() != "default")
+            .cloned()
+            .collect::<Vec<String>>();
+
+        feature_powerset(&features, self.config.feature_depth)
+    }
+
+    /// Tries to compile the project of the this toml file.
+    /// Tests and examples are always built when a dev-dependency is permutated.
+    /// If packages are given, those workspace members are compiled instead of the package of this toml file.
+    pub fn try_compile(
+        &self,
+        kind: DependencyKind,
+        packages: &[String],
+        features: &[String],
+    ) -> anyhow::Result<()> {
+        self.compile(kind, packages, features, Box::new(Vec::new()))
+    }
+
+    /// Compiles like `try_compile`, but returns the output of the compiler if it fails to compile.
+    /// Returns `None` if it compiles.
+    pub fn compiler_errors(
+        &self,
+        kind: DependencyKind,
+        packages: &[String],
+        features: &[String],
+    ) -> Option<String> {
+        let output = CapturedOutput::default();
+
+        match self.compile(kind, packages, features, Box::new(output.clone())) {
+            Ok(_) => None,
+            Err(e) => Some(format!("{}\n{e}", output.contents())),
+        }
+    }
+
+    fn compile(
+        &self,
+        kind: DependencyKind,
+        packages: &[String],
+        features: &[String],
+        output: Box<dyn Write>,
+    ) -> anyhow::Result<()> {
         let config = Config::default()?;
 
-        let buffer = Box::new(Vec::new());
-        *config.shell() = Shell::from_write(buffer);
+        *config.shell() = Shell::from_write(output);
         config.shell().set_verbosity(Verbosity::Quiet);
 
         let mut compile_options = CompileOptions::new(&config, CompileMode::Build)?;
@@ -178,11 +337,11 @@ impl CargoProject {
                 *lib = cargo::ops::LibRule::True;
             }
 
-            if self.config.build_examples {
+            if self.config.build_examples || kind == DependencyKind::Development {
                 *examples = cargo::ops::FilterRule::All;
             }
 
-            if self.config.build_tests {
+            if self.config.build_tests || kind == DependencyKind::Development {
                 *tests = cargo::ops::FilterRule::All;
             }
 
@@ -191,6 +350,23 @@ impl CargoProject {
             }
         }
 
+        if !packages.is_empty() {
+            compile_options.spec = Packages::Packages(packages.to_vec());
+        }
+
+        // The configured own features, and the ones that have to be enabled to exercise the permutated dependency features.
+        // The configured features are declared by the package of this toml file, other packages are compiled without those.
+        let features = if packages.is_empty() {
+            [self.config.features.as_slice(), features].concat()
+        } else {
+            features.to_vec()
+        };
+        compile_options.cli_features = CliFeatures::from_command_line(
+            &features,
+            self.config.all_features,
+            !self.config.no_default_features,
+        )?;
+
         let workspace = Workspace::new(&self.toml_path(), &config)?;
 
         cargo::ops::compile(&workspace, &compile_options)
@@ -201,10 +377,140 @@ impl CargoProject {
 
     /// Gathers metadata of the toml file and returns the crate dependencies with their features.
     pub fn gather_meta_data(&self) -> CrateDependencies {
+        let mut crate_dependencies =
+            self.gather_dependency_tables(self.in_memory_toml.dependency_tables());
+        self.gather_feature_references(&mut crate_dependencies);
+        crate_dependencies
+    }
+
+    /// Gathers the `dep/feature` references in the `[features]` table of this toml file.
+    /// Those are permutated per own feature, named after the feature declaring them.
+    fn gather_feature_references(&self, crate_dependencies: &mut CrateDependencies) {
+        for (feature, references) in self.in_memory_toml.feature_dependency_references() {
+            let permutation_features = references
+                .into_iter()
+                .filter(|reference| {
+                    referenced_dependency(reference).is_some_and(|dependency| {
+                        !self
+                            .config
+                            .skip_dependencies
+                            .iter()
+                            .any(|skip| skip == dependency)
+                    })
+                })
+                .map(|reference| reference.to_string())
+                .collect::<HashSet<String>>();
+
+            if !permutation_features.is_empty() {
+                let dependency_key = DependencyKey::new(DependencyKind::Feature, None, feature);
+                crate_dependencies
+                    .active_features
+                    .insert(dependency_key.clone(), vec![feature.to_string()]);
+                crate_dependencies
+                    .dependency_features
+                    .insert(dependency_key, permutation_features);
+            }
+        }
+    }
+
+    /// Gathers metadata of the `[workspace.dependencies]` definitions in this workspace root toml file.
+    /// Only definitions that are inherited by at least one workspace member are returned.
+    pub fn gather_workspace_meta_data(&self) -> anyhow::Result<CrateDependencies> {
+        let tables = self
+            .in_memory_toml
+            .workspace_dependency_table()
+            .map(|table| vec![(DependencyKind::Workspace, None, table)])
+            .unwrap_or_default();
+
+        let mut crate_dependencies = self.gather_dependency_tables(tables);
+
+        self.inheriting_members(&mut crate_dependencies)?;
+        crate_dependencies
+            .dependency_features
+            .retain(|dependency_key, _| {
+                crate_dependencies.inherited_by.contains_key(dependency_key)
+            });
+
+        Ok(crate_dependencies)
+    }
+
+    /// Records the workspace members inheriting each `[workspace.dependencies]` definition
+    /// and the definitions inherited as a dev-dependency.
+    fn inheriting_members(&self, crate_dependencies: &mut CrateDependencies) -> anyhow::Result<()> {
+        for (_, member_path) in self.workspace_members()? {
+            let toml_contents = fs::read_to_string(member_path.join("Cargo.toml"))?;
+            let member = TomlInMemory::new(toml_contents)?;
+
+            let member_name = match &member.package {
+                Some(package) => package.name.clone(),
+                None => continue,
+            };
+
+            for (kind, _, table) in member.dependency_tables() {
+                for (name, dependency) in table {
+                    if let cargo_toml::Dependency::Inherited(_) = dependency {
+                        let dependency_key =
+                            DependencyKey::new(DependencyKind::Workspace, None, name);
+
+                        if kind == DependencyKind::Development {
+                            crate_dependencies
+                                .inherited_as_dev_dependency
+                                .insert(dependency_key.clone());
+                        }
+
+                        let members = crate_dependencies
+                            .inherited_by
+                            .entry(dependency_key)
+                            .or_default();
+
+                        if !members.contains(&member_name) {
+                            members.push(member_name.clone());
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Cross-references the own `[features]` of this crate with the `cfg(feature = ...)` references in its sources.
+    /// Returns `None` if the own features analysis is not configured or this is a virtual manifest.
+    pub fn own_features(&self) -> anyhow::Result<Option<OwnFeatures>> {
+        if !self.config.own_features_analysis || self.in_memory_toml.package.is_none() {
+            return Ok(None);
+        }
+
+        let metadata = cargo_metadata::MetadataCommand::new()
+            .manifest_path(self.toml_path())
+            .current_dir(self.workspace_path())
+            .no_deps()
+            .exec()?;
+
+        // The root package is the one of the workspace root, the package of a member is found by its manifest.
+        let toml_path = fs::canonicalize(self.toml_path())?;
+        let package = metadata
+            .packages
+            .iter()
+            .find(|package| package.manifest_path.as_std_path() == toml_path);
+
+        match package {
+            Some(package) => Ok(Some(analyze_own_features(package, &self.workspace_path())?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Gathers metadata of the toml file and returns the dependencies of the given tables with their features.
+    fn gather_dependency_tables(
+        &self,
+        tables: Vec<(DependencyKind, Option<&str>, &DepsSet)>,
+    ) -> CrateDependencies {
         log::debug!("Fetching crate metadata...");
 
+        // All features are resolved, so that optional dependencies are part of the resolve graph.
         let metadata = cargo_metadata::MetadataCommand::new()
             .manifest_path(self.toml_path())
+            .features(CargoOpt::AllFeatures)
             .current_dir(self.workspace_path())
             .exec()
             .expect("failed to fetch metadata");
@@ -213,7 +519,7 @@ impl CargoProject {
 
         log::debug!("Analyzing metadata...");
 
-        let mut crate_dependencies = self.crate_dependencies();
+        let mut crate_dependencies = self.crate_dependencies(tables);
 
         self.get_permutable_features(&metadata, &mut crate_dependencies);
 
@@ -222,29 +528,39 @@ impl CargoProject {
         crate_dependencies
     }
 
-    /// Returns the crate dependencies of this toml file.
-    fn crate_dependencies(&self) -> CrateDependencies {
+    /// Returns the crate dependencies declared in the given tables of this toml file.
+    fn crate_dependencies(
+        &self,
+        tables: Vec<(DependencyKind, Option<&str>, &DepsSet)>,
+    ) -> CrateDependencies {
         let mut crate_dependencies = CrateDependencies::new();
 
-        for (name, dep) in self.in_memory_toml.dependencies.clone() {
-            let dependency = match dep {
-                cargo_toml::Dependency::Simple(version) => {
-                    // We replace simple notation by detailed notation to make it easier to work with.
-                    cargo_toml::DependencyDetail {
-                        version: Some(version),
-                        default_features: true,
-                        ..Default::default()
+        for (kind, target, table) in tables {
+            for (name, dep) in table.clone() {
+                let dependency = match dep {
+                    cargo_toml::Dependency::Simple(version) => {
+                        // We replace simple notation by detailed notation to make it easier to work with.
+                        cargo_toml::DependencyDetail {
+                            version: Some(version),
+                            default_features: true,
+                            ..Default::default()
+                        }
                     }
-                }
-                cargo_toml::Dependency::Detailed(detailed) => detailed,
-                cargo_toml::Dependency::Inherited(_inherited) => cargo_toml::DependencyDetail {
-                    ..Default::default()
-                },
-            };
+                    cargo_toml::Dependency::Detailed(detailed) => detailed,
+                    // Inherited dependencies can only add features and mark the dependency optional.
+                    cargo_toml::Dependency::Inherited(inherited) => cargo_toml::DependencyDetail {
+                        features: inherited.features,
+                        optional: inherited.optional,
+                        inherited: true,
+                        default_features: false,
+                        ..Default::default()
+                    },
+                };
 
-            crate_dependencies
-                .dependencies
-                .insert(name.clone(), dependency);
+                crate_dependencies
+                    .dependencies
+                    .insert(DependencyKey::new(kind, target, &name), dependency);
+            }
         }
 
         crate_dependencies
@@ -267,107 +583,387 @@ impl CargoProject {
     ) {
         log::debug!("Gathering dependencies their features...");
 
-        for package in &metadata.packages {
-            let package_name = package.name.clone();
+        crate_dependencies.separate_host_features = separates_host_features(&self.toml_path())
+            .unwrap_or_else(|e| {
+                log::warn!("Failed to determine the feature resolver, assuming resolver 2. {e}");
+                true
+            });
+
+        let root_package = metadata.root_package();
+
+        // The dependencies of a proc-macro crate are compiled for the host, like build-dependencies are.
+        let is_proc_macro = root_package.is_some_and(|package| {
+            package
+                .targets
+                .iter()
+                .any(|target| target.kind.iter().any(|kind| kind == "proc-macro"))
+        });
+
+        for (dependency_key, crate_dependency) in &crate_dependencies.dependencies {
+            // The dependency as resolved by cargo, this also contains the features enabled by the workspace.
+            let resolved_dependency = root_package.and_then(|package| {
+                package
+                    .dependencies
+                    .iter()
+                    .find(|dependency| dependency_key.matches_metadata(dependency))
+            });
+
+            // Renamed dependencies `x = { package = "y" }` are declared under a different key than the package name.
+            let package_name = match resolved_dependency {
+                Some(resolved_dependency) => resolved_dependency.name.clone(),
+                None => crate_dependency
+                    .package
+                    .clone()
+                    .unwrap_or_else(|| dependency_key.name.clone()),
+            };
 
-            if self.config.skip_dependencies.contains(&package_name) {
+            if self.config.skip_dependencies.contains(&package_name)
+                || self.config.skip_dependencies.contains(&dependency_key.name)
+            {
                 continue;
             }
 
-            if let Some(crate_dependency) = crate_dependencies.dependencies.get(&package_name) {
-                // The manually entered features in toml file.
-                let manual_selected_features: HashSet<String> =
-                    HashSet::from_iter(crate_dependency.features.clone().into_iter());
-
-                // All features of each dependency.
-                let dependency_features = package.features.clone();
-
-                // The features that will be applicable to removal.
-                let mut permutation_features = HashSet::new();
-
-                let has_manual_selected_features = !manual_selected_features.is_empty();
-                let has_default_features = crate_dependency.default_features;
-
-                // Gather the features that will be applicable to removal.
-                // Feature flags might contain a collection of other features.
-
-                fn gather_manual_selected_features(
-                    permutation_features: &mut HashSet<String>,
-                    manual_selected_features: &HashSet<String>,
-                    dependency_features: &HashMap<String, Vec<String>>,
-                ) {
-                    for manual_selected_feature in manual_selected_features {
-                        if let Some(custom_feature_list) =
-                            dependency_features.get(manual_selected_feature)
-                        {
-                            // Features can have 0-n other features as dependencies e.g. 'default=[x,y]'.
-
-                            for custom_feature_list_feature in custom_feature_list {
-                                // Must be a public facing feature.
-                                if dependency_features.contains_key(custom_feature_list_feature) {
-                                    permutation_features
-                                        .insert(custom_feature_list_feature.clone());
-                                }
-                            }
-                        }
-
-                        // Also insert the custom feature itself.
-                        permutation_features.insert(manual_selected_feature.clone());
-                    }
+            let package = match resolve_package(
+                metadata,
+                dependency_key,
+                crate_dependency,
+                resolved_dependency,
+                &package_name,
+            ) {
+                Some(package) => package,
+                None => {
+                    log::debug!("Package '{package_name}' of dependency '{dependency_key}' not found in the crate metadata.");
+                    continue;
                 }
+            };
 
-                fn gather_default_enabled_features(
-                    permutation_features: &mut HashSet<String>,
-                    dependency_features: &HashMap<String, Vec<String>>,
-                ) {
-                    if let Some(default_features) = dependency_features.get("default") {
-                        for default_feature in default_features {
-                            // Must be a public facing feature.
-                            if dependency_features.contains_key(default_feature) {
-                                permutation_features.insert(default_feature.clone());
-                            }
-                        }
-                    }
+            // The manually entered features in toml file.
+            let manual_selected_features: HashSet<String> =
+                HashSet::from_iter(crate_dependency.features.clone());
+
+            // All features of each dependency.
+            let feature_graph = FeatureGraph::new(package);
+
+            // The features that will be applicable to removal.
+            let mut permutation_features = HashSet::new();
+
+            let has_manual_selected_features = !manual_selected_features.is_empty();
+            let has_default_features = crate_dependency.default_features;
+
+            // Test the various ways features can be enabled/disabled and gather the explicitly or implicitly enabled features.
+
+            if !has_default_features && !has_manual_selected_features { /* do nothing as there are no features specified */
+            } else if !has_default_features && has_manual_selected_features {
+                /* permutate features */
+                gather_manual_selected_features(
+                    &mut permutation_features,
+                    &manual_selected_features,
+                    &feature_graph,
+                );
+            } else if has_default_features && !has_manual_selected_features {
+                /* permutate default features */
+                gather_default_enabled_features(&mut permutation_features, &feature_graph);
+            } else if has_default_features && has_manual_selected_features {
+                /* permutate default features and custom features */
+
+                gather_manual_selected_features(
+                    &mut permutation_features,
+                    &manual_selected_features,
+                    &feature_graph,
+                );
+                gather_default_enabled_features(&mut permutation_features, &feature_graph);
+            }
+
+            // The features only enabled through the default features, those are tried to be removed as a whole first.
+            // Inherited dependencies get their default features from the workspace, thus those are left as they are.
+            if has_default_features && !crate_dependency.inherited {
+                let mut default_enabled_features = HashSet::new();
+                gather_default_enabled_features(&mut default_enabled_features, &feature_graph);
+
+                let mut explicit_features = HashSet::new();
+                gather_manual_selected_features(
+                    &mut explicit_features,
+                    &manual_selected_features,
+                    &feature_graph,
+                );
+
+                default_enabled_features.retain(|feature| !explicit_features.contains(feature));
+
+                if !default_enabled_features.is_empty() {
+                    crate_dependencies
+                        .default_features
+                        .insert(dependency_key.clone(), default_enabled_features);
                 }
+            }
 
-                // Test the various ways features can be enabled/disabled and gather the explicitly or implicitly enabled features.
+            // Inherited dependencies `x = { workspace = true }` can only add features on top of the ones
+            // enabled by the `[workspace.dependencies]` definition, those can not be removed from this crate.
+            if crate_dependency.inherited {
+                let mut workspace_enabled_features = HashSet::new();
 
-                if !has_default_features && !has_manual_selected_features { /* do nothing as there are no features specified */
-                } else if !has_default_features && has_manual_selected_features {
-                    /* permutate features */
-                    gather_manual_selected_features(
-                        &mut permutation_features,
-                        &manual_selected_features,
-                        &dependency_features,
-                    );
-                } else if has_default_features && !has_manual_selected_features {
-                    /* permutate default features */
-                    gather_default_enabled_features(
-                        &mut permutation_features,
-                        &dependency_features,
-                    );
-                } else if has_default_features && has_manual_selected_features {
-                    /* permutate default features and custom features */
+                if let Some(resolved_dependency) = resolved_dependency {
+                    // The resolved features are the workspace features and the ones of this crate combined.
+                    let workspace_features = resolved_dependency
+                        .features
+                        .iter()
+                        .filter(|feature| !manual_selected_features.contains(*feature))
+                        .cloned()
+                        .collect();
 
                     gather_manual_selected_features(
-                        &mut permutation_features,
-                        &manual_selected_features,
-                        &dependency_features,
+                        &mut workspace_enabled_features,
+                        &workspace_features,
+                        &feature_graph,
                     );
-                    gather_default_enabled_features(
-                        &mut permutation_features,
-                        &dependency_features,
+
+                    if resolved_dependency.uses_default_features {
+                        gather_default_enabled_features(
+                            &mut workspace_enabled_features,
+                            &feature_graph,
+                        );
+                    }
+                }
+
+                permutation_features
+                    .retain(|feature| !workspace_enabled_features.contains(feature));
+
+                crate_dependencies
+                    .workspace_features
+                    .insert(dependency_key.clone(), workspace_enabled_features);
+                crate_dependencies
+                    .packages
+                    .insert(dependency_key.clone(), package.clone());
+            }
+
+            // Host-side dependencies are matched against the host platform.
+            // With resolver 2 their features are also resolved separately from the target-side ones.
+            if dependency_key.kind == DependencyKind::Build
+                || (is_proc_macro && dependency_key.kind == DependencyKind::Normal)
+            {
+                crate_dependencies
+                    .host_dependencies
+                    .insert(dependency_key.clone());
+            }
+
+            // Removals on workspace-internal path dependencies are verified against the members depending on this crate as well.
+            // Workspace dependency definitions are verified against the members inheriting them instead.
+            if dependency_key.kind != DependencyKind::Workspace
+                && metadata.workspace_members.contains(&package.id)
+            {
+                crate_dependencies.internal_dependents.insert(
+                    dependency_key.clone(),
+                    root_package
+                        .map(|root_package| workspace_dependents(metadata, &root_package.id))
+                        .unwrap_or_default(),
+                );
+            }
+
+            // Optional dependencies are only compiled if one of the own features activating them is enabled.
+            if crate_dependency.optional {
+                if let Some(package) = root_package {
+                    crate_dependencies.active_features.insert(
+                        dependency_key.clone(),
+                        activating_features(package, &dependency_key.name),
                     );
                 }
+            }
 
-                // If no features were found then we dont have to record this dependency.
-                if !permutation_features.is_empty() {
-                    crate_dependencies
-                        .dependency_features
-                        .insert(package_name.to_string(), permutation_features);
+            // If no features were found then we dont have to record this dependency.
+            if !permutation_features.is_empty() {
+                crate_dependencies.feature_activations.insert(
+                    dependency_key.clone(),
+                    feature_graph.activations(&permutation_features),
+                );
+                crate_dependencies
+                    .packages
+                    .insert(dependency_key.clone(), package.clone());
+                crate_dependencies
+                    .dependency_features
+                    .insert(dependency_key.clone(), permutation_features);
+            }
+        }
+    }
+}
+
+/// Returns if the features of host-side dependencies are resolved separately from the target-side ones.
+/// That is the case with resolver "2", the default of edition 2021 packages, with resolver "1" the features are unified.
+/// The resolver of the workspace root applies to all members.
+fn separates_host_features(toml_path: &Path) -> anyhow::Result<bool> {
+    let config = Config::default()?;
+    let workspace = Workspace::new(toml_path, &config)?;
+
+    Ok(workspace.resolve_behavior() == ResolveBehavior::V2)
+}
+
+/// Returns the names of the workspace members that depend on the given package, directly or transitively.
+fn workspace_dependents(metadata: &Metadata, package_id: &PackageId) -> Vec<String> {
+    let nodes = match &metadata.resolve {
+        Some(resolve) => &resolve.nodes,
+        None => return vec![],
+    };
+
+    let depends_on = |member: &PackageId| {
+        let mut visited = HashSet::new();
+        let mut queue = vec![member];
+
+        while let Some(id) = queue.pop() {
+            if !visited.insert(id) {
+                continue;
+            }
+
+            if let Some(node) = nodes.iter().find(|node| &node.id == id) {
+                if node.dependencies.contains(package_id) {
+                    return true;
                 }
+                queue.extend(node.dependencies.iter());
             }
         }
+
+        false
+    };
+
+    let mut dependents = metadata
+        .workspace_members
+        .iter()
+        .filter(|member| *member != package_id && depends_on(member))
+        .filter_map(|member| {
+            metadata
+                .packages
+                .iter()
+                .find(|package| &package.id == member)
+        })
+        .map(|package| package.name.clone())
+        .collect::<Vec<String>>();
+
+    dependents.sort();
+    dependents
+}
+
+/// Returns the own features of the package that activate the given optional dependency, sorted by name.
+/// Code using the dependency can be gated behind any of those, thus all of them have to be enabled.
+fn activating_features(package: &Package, dependency_name: &str) -> Vec<String> {
+    let feature_graph = FeatureGraph::new(package);
+
+    let mut features = package
+        .features
+        .keys()
+        .filter(|feature| feature.as_str() != "default")
+        .filter(|feature| {
+            feature_graph
+                .activation([feature.as_str()])
+                .optional_dependencies
+                .contains(dependency_name)
+        })
+        .cloned()
+        .collect::<Vec<String>>();
+
+    features.sort();
+    features
+}
+
+/// Resolves the package a dependency links to.
+///
+/// The lockfile can contain multiple versions of the same crate,
+/// thus the package is looked up by the package id the resolve graph links to the dependency.
+/// Dependencies without a resolve node, like the `[workspace.dependencies]` definitions,
+/// are matched to the highest version matching their version requirement.
+fn resolve_package<'a>(
+    metadata: &'a Metadata,
+    dependency_key: &DependencyKey,
+    crate_dependency: &DependencyDetail,
+    resolved_dependency: Option<&cargo_metadata::Dependency>,
+    package_name: &str,
+) -> Option<&'a Package> {
+    let package = |id: &PackageId| metadata.packages.iter().find(|package| &package.id == id);
+
+    let root_node = metadata.resolve.as_ref().and_then(|resolve| {
+        let root = resolve.root.as_ref()?;
+        resolve.nodes.iter().find(|node| &node.id == root)
+    });
+
+    if let (Some(root_node), Some(resolved_dependency)) = (root_node, resolved_dependency) {
+        // The node dependency name is the name of the crate in code, with dashes replaced by underscores.
+        let rename = resolved_dependency
+            .rename
+            .as_ref()
+            .map(|rename| rename.replace('-', "_"));
+
+        return root_node
+            .deps
+            .iter()
+            .filter(|node_dependency| {
+                node_dependency
+                    .dep_kinds
+                    .iter()
+                    .any(|dep_kind| dependency_key.matches_table(&dep_kind.kind, &dep_kind.target))
+            })
+            .filter(|node_dependency| match &rename {
+                Some(rename) => &node_dependency.name == rename,
+                None => true,
+            })
+            .filter_map(|node_dependency| package(&node_dependency.pkg))
+            .find(|package| {
+                package.name == package_name && resolved_dependency.req.matches(&package.version)
+            });
+    }
+
+    let version_requirement = crate_dependency
+        .version
+        .as_deref()
+        .and_then(|version| VersionReq::parse(version).ok());
+
+    metadata
+        .packages
+        .iter()
+        .filter(|package| package.name == package_name)
+        .filter(|package| match &version_requirement {
+            Some(version_requirement) => version_requirement.matches(&package.version),
+            None => true,
+        })
+        .max_by(|a, b| a.version.cmp(&b.version))
+}
+
+// Gather the features that will be applicable to removal.
+// Feature flags might contain a collection of other features, those are followed transitively.
+
+fn gather_manual_selected_features(
+    permutation_features: &mut HashSet<String>,
+    manual_selected_features: &HashSet<String>,
+    feature_graph: &FeatureGraph,
+) {
+    // Features can have 0-n other features as dependencies e.g. 'full=[net,fs]'.
+    let activation = feature_graph.activation(manual_selected_features.iter().map(String::as_str));
+    permutation_features.extend(activation.features);
+
+    // Also insert the custom features themselves.
+    permutation_features.extend(manual_selected_features.iter().cloned());
+}
+
+fn gather_default_enabled_features(
+    permutation_features: &mut HashSet<String>,
+    feature_graph: &FeatureGraph,
+) {
+    let activation = feature_graph.activation(["default"]);
+    permutation_features.extend(activation.features);
+}
+
+/// A writer capturing the output of the compiler.
+#[derive(Clone, Default)]
+struct CapturedOutput(Rc<RefCell<Vec<u8>>>);
+
+impl CapturedOutput {
+    fn contents(&self) -> String {
+        String::from_utf8_lossy(&self.0.borrow()).to_string()
+    }
+}
+
+impl Write for CapturedOutput {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
     }
 }
 
@@ -375,10 +971,58 @@ impl Drop for CargoProject {
     fn drop(&mut self) {
         // By default we reset the toml always after we mutated it for analyzing purposes.
         // Could be made optional later.
-        let mut permutated_toml_file = File::create(&self.toml_path()).unwrap();
+        let mut permutated_toml_file = File::create(self.toml_path()).unwrap();
         permutated_toml_file
             .write_all(self.original.as_bytes())
             .unwrap();
         log::debug!("Resetting toml file to original.");
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes a package with the given manifest to a temporary directory and returns its manifest path.
+    fn package(name: &str, manifest: &str) -> Box<Path> {
+        let directory =
+            std::env::temp_dir().join(format!("unused-features-{name}-{}", std::process::id()));
+        fs::create_dir_all(directory.join("src")).unwrap();
+        fs::write(directory.join("src").join("lib.rs"), "").unwrap();
+        fs::write(directory.join("Cargo.toml"), manifest).unwrap();
+
+        directory.join("Cargo.toml").into_boxed_path()
+    }
+
+    fn separates_host_features_of(name: &str, manifest: &str) -> bool {
+        let toml_path = package(name, manifest);
+        let separates = separates_host_features(&toml_path);
+        fs::remove_dir_all(toml_path.parent().unwrap()).unwrap();
+
+        separates.unwrap()
+    }
+
+    #[test]
+    fn resolver_1_unifies_host_features() {
+        assert!(!separates_host_features_of(
+            "resolver-1",
+            "[package]\nname = \"a\"\nversion = \"0.1.0\"\nedition = \"2018\"\n",
+        ));
+        assert!(!separates_host_features_of(
+            "explicit-resolver-1",
+            "[package]\nname = \"a\"\nversion = \"0.1.0\"\nedition = \"2021\"\nresolver = \"1\"\n",
+        ));
+    }
+
+    #[test]
+    fn resolver_2_separates_host_features() {
+        assert!(separates_host_features_of(
+            "resolver-2",
+            "[package]\nname = \"a\"\nversion = \"0.1.0\"\nedition = \"2021\"\n",
+        ));
+        assert!(separates_host_features_of(
+            "explicit-resolver-2",
+            "[package]\nname = \"a\"\nversion = \"0.1.0\"\nedition = \"2018\"\nresolver = \"2\"\n",
+        ));
+    }
+}
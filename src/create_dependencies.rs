@@ -1,13 +1,108 @@
-use std::collections::{HashMap, HashSet};
+use std::{
+    collections::{HashMap, HashSet},
+    str::FromStr,
+};
 
+use cargo_metadata::Package;
 use cargo_toml::DependencyDetail;
 
+use crate::{dependency_kind::DependencyKind, feature_graph::FeatureActivation};
+
+/// Identifies a dependency by the table it is declared in and its name.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct DependencyKey {
+    /// The table the dependency is declared in.
+    pub kind: DependencyKind,
+    /// The platform of the `[target.'...'.dependencies]` table the dependency is declared in, if any.
+    pub target: Option<String>,
+    /// The name of the dependency in the toml file.
+    pub name: String,
+}
+
+impl DependencyKey {
+    pub fn new(kind: DependencyKind, target: Option<&str>, name: &str) -> Self {
+        Self {
+            kind,
+            target: target.map(|target| target.to_string()),
+            name: name.to_string(),
+        }
+    }
+}
+
+impl DependencyKey {
+    /// Returns if the given `cargo metadata` dependency is the one declared under this key.
+    pub fn matches_metadata(&self, dependency: &cargo_metadata::Dependency) -> bool {
+        let name = dependency.rename.as_ref().unwrap_or(&dependency.name);
+
+        &self.name == name && self.matches_table(&dependency.kind, &dependency.target)
+    }
+
+    /// Returns if the given `cargo metadata` dependency kind and platform are the table this key is declared in.
+    pub fn matches_table(
+        &self,
+        kind: &cargo_metadata::DependencyKind,
+        target: &Option<cargo_platform::Platform>,
+    ) -> bool {
+        let kind = match kind {
+            cargo_metadata::DependencyKind::Normal => DependencyKind::Normal,
+            cargo_metadata::DependencyKind::Development => DependencyKind::Development,
+            cargo_metadata::DependencyKind::Build => DependencyKind::Build,
+            _ => return false,
+        };
+
+        let own_target = self
+            .target
+            .as_deref()
+            .map(cargo_platform::Platform::from_str)
+            .transpose()
+            .ok()
+            .flatten();
+
+        self.kind == kind && &own_target == target
+    }
+}
+
+impl std::fmt::Display for DependencyKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (&self.target, self.kind) {
+            (Some(target), kind) => {
+                write!(f, "target.'{}'.{}.{}", target, kind.table_name(), self.name)
+            }
+            // Keep regular dependencies readable, those are the most common.
+            (None, DependencyKind::Normal) => write!(f, "{}", self.name),
+            (None, kind) => write!(f, "{}.{}", kind.table_name(), self.name),
+        }
+    }
+}
+
 /// Crate dependencies and their features.
 pub struct CrateDependencies {
     /// The dependencies of the crate.
-    pub(crate) dependencies: HashMap<String, DependencyDetail>,
+    pub(crate) dependencies: HashMap<DependencyKey, DependencyDetail>,
     /// The dependencies by name and their features.
-    pub(crate) dependency_features: HashMap<String, HashSet<String>>,
+    pub(crate) dependency_features: HashMap<DependencyKey, HashSet<String>>,
+    /// The features only enabled through the default features of the dependency, not listed explicitly.
+    pub(crate) default_features: HashMap<DependencyKey, HashSet<String>>,
+    /// The features enabled by the `[workspace.dependencies]` definition of inherited dependencies.
+    /// Those can only be removed in the workspace root and are not permutated.
+    pub(crate) workspace_features: HashMap<DependencyKey, HashSet<String>>,
+    /// The packages the dependencies are resolved to.
+    /// The package name differs from the name in the toml file for renamed dependencies.
+    pub(crate) packages: HashMap<DependencyKey, Package>,
+    /// What each permutated feature activates in the dependency.
+    pub(crate) feature_activations: HashMap<DependencyKey, HashMap<String, FeatureActivation>>,
+    /// The own features that have to be enabled to compile the dependency, e.g. for optional dependencies.
+    pub(crate) active_features: HashMap<DependencyKey, Vec<String>>,
+    /// The dependencies compiled for the host, the build-dependencies and the dependencies of a proc-macro crate.
+    pub(crate) host_dependencies: HashSet<DependencyKey>,
+    /// The features of the host dependencies are resolved separately from the target-side ones, as resolver 2 does.
+    pub(crate) separate_host_features: bool,
+    /// The workspace members depending on this crate, directly or transitively, for each workspace-internal path dependency.
+    pub(crate) internal_dependents: HashMap<DependencyKey, Vec<String>>,
+    /// The workspace members inheriting a `[workspace.dependencies]` definition.
+    pub(crate) inherited_by: HashMap<DependencyKey, Vec<String>>,
+    /// The `[workspace.dependencies]` definitions inherited as a dev-dependency by at least one member.
+    pub(crate) inherited_as_dev_dependency: HashSet<DependencyKey>,
 }
 
 impl CrateDependencies {
@@ -15,6 +110,16 @@ impl CrateDependencies {
         Self {
             dependencies: HashMap::default(),
             dependency_features: HashMap::default(),
+            default_features: HashMap::default(),
+            workspace_features: HashMap::default(),
+            packages: HashMap::default(),
+            feature_activations: HashMap::default(),
+            active_features: HashMap::default(),
+            host_dependencies: HashSet::default(),
+            separate_host_features: true,
+            internal_dependents: HashMap::default(),
+            inherited_by: HashMap::default(),
+            inherited_as_dev_dependency: HashSet::default(),
         }
     }
 }
@@ -23,9 +128,9 @@ impl std::fmt::Debug for CrateDependencies {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         writeln!(f, "Found the following dependencies:")?;
 
-        for (name, features) in &self.dependency_features {
+        for (key, features) in &self.dependency_features {
             let features = features.clone().into_iter().collect::<Vec<String>>();
-            writeln!(f, "{}=[{}]", name, features.join(","))?;
+            writeln!(f, "{}=[{}]", key, features.join(","))?;
         }
         Ok(())
     }
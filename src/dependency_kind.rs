@@ -0,0 +1,38 @@
+use serde::{Deserialize, Serialize};
+
+/// The kind of a dependency, which corresponds to the toml table it is declared in.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[serde(rename_all = "kebab-case")]
+pub enum DependencyKind {
+    /// Declared in `[dependencies]`.
+    #[default]
+    Normal,
+    /// Declared in `[dev-dependencies]`.
+    /// Dev-dependencies are only used by tests and examples.
+    Development,
+    /// Declared in `[build-dependencies]`.
+    Build,
+    /// Declared in `[workspace.dependencies]` of the workspace root.
+    Workspace,
+    /// A `dep/feature` reference in the `[features]` table of the crate, named after the feature declaring it.
+    Feature,
+}
+
+impl DependencyKind {
+    /// Returns the name of the toml table in which this kind of dependency is declared.
+    pub fn table_name(&self) -> &'static str {
+        match self {
+            DependencyKind::Normal => "dependencies",
+            DependencyKind::Development => "dev-dependencies",
+            DependencyKind::Build => "build-dependencies",
+            DependencyKind::Workspace => "workspace.dependencies",
+            DependencyKind::Feature => "features",
+        }
+    }
+}
+
+impl std::fmt::Display for DependencyKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.table_name())
+    }
+}
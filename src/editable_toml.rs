@@ -1,9 +1,16 @@
 use toml_edit::{Array, Document, Formatted, InlineTable, Item, Value};
 
+use crate::{
+    create_dependencies::DependencyKey, dependency_kind::DependencyKind,
+    feature_graph::is_dependency_feature_reference,
+};
+
 /// An in memory toml file that can be used to edit the toml file without altering formatting, spaces, comments, etc..
 pub struct TomlEdit {
     pub toml_document: Document,
-    original_dependencies: Item,
+    /// The document `reset` returns to, the original one unless changes are kept.
+    baseline_document: Document,
+    original_document: Document,
 }
 
 impl TomlEdit {
@@ -11,35 +18,69 @@ impl TomlEdit {
     pub fn new(toml_contents: String) -> anyhow::Result<Self> {
         let toml_document = toml_contents.parse::<Document>()?;
 
-        let original_dependencies = toml_document
-            .get("dependencies")
-            .ok_or_else(|| anyhow::anyhow!("Dependencies tag not found in toml document"))?
-            .clone();
-
         Ok(Self {
+            baseline_document: toml_document.clone(),
+            original_document: toml_document.clone(),
             toml_document,
-            original_dependencies,
         })
     }
 
     /// Replaces the dependency features with the given features.
     pub fn replace_dependency_features(
         &mut self,
-        dependency: &String,
+        dependency: &DependencyKey,
         features: Vec<String>,
     ) -> anyhow::Result<()> {
-        // Get dependencies section.
-        let dependencies = self
-            .toml_document
-            .get_mut("dependencies")
-            .ok_or_else(|| anyhow::anyhow!("Dependencies tag not found in toml document"))?;
+        let table_name = dependency.kind.table_name();
+
+        // Own features `json = ["dep:serde_json", "reqwest/json"]`, only the `dep/feature` references are replaced.
+        if dependency.kind == DependencyKind::Feature {
+            let values = self
+                .toml_document
+                .get_mut(table_name)
+                .and_then(|features| features.get_mut(&dependency.name))
+                .and_then(|values| values.as_array_mut())
+                .ok_or_else(|| anyhow::anyhow!("Feature not found in toml document"))?;
+
+            // Remove back to front so that the indices of the remaining values stay valid.
+            for index in (0..values.len()).rev() {
+                let removed = values
+                    .get(index)
+                    .and_then(|value| value.as_str())
+                    .is_some_and(|value| {
+                        is_dependency_feature_reference(value)
+                            && !features.iter().any(|feature| feature == value)
+                    });
+
+                if removed {
+                    values.remove(index);
+                }
+            }
+
+            return Ok(());
+        }
+
+        // Get dependencies section, either a top level one, the one of a `[target.'...']` table or the workspace one.
+        let dependencies = match (&dependency.target, dependency.kind) {
+            (_, DependencyKind::Workspace) => self
+                .toml_document
+                .get_mut("workspace")
+                .and_then(|workspace| workspace.get_mut("dependencies")),
+            (Some(target), _) => self
+                .toml_document
+                .get_mut("target")
+                .and_then(|targets| targets.get_mut(target))
+                .and_then(|target| target.get_mut(table_name)),
+            (None, _) => self.toml_document.get_mut(table_name),
+        }
+        .ok_or_else(|| anyhow::anyhow!("{} tag not found in toml document", table_name))?;
 
         // Find dependency.
         let dependency = dependencies
-            .get_mut(dependency)
+            .get_mut(&dependency.name)
             .ok_or_else(|| anyhow::anyhow!("Dependency not found in toml document"))?;
 
-        let features_to_add = Array::from_iter(features.into_iter());
+        let features_to_add = Array::from_iter(features);
 
         // Short dependency notation `x = "1.0"`
         if let Some(version) = dependency.as_str() {
@@ -68,39 +109,77 @@ impl TomlEdit {
             return Ok(());
         }
 
-        // Long dependency notation `x = { version = "1.0"}`
-        if let Some(dependency_table) = dependency.as_inline_table_mut() {
-            // Check if the 'default-features' tag should be inserted.
-            if let Some(default_features) = dependency_table.get_mut("default-features") {
-                // Overwrite the current 'default-features' tag with the new one.
-                *default_features = Value::Boolean(Formatted::new(false));
+        // Long dependency notation, either an inline table `x = { version = "1.0" }`,
+        // a standard table `[dependencies.x]` or dotted keys `x.version = "1.0"`.
+        let is_inline_table = dependency.is_inline_table();
+
+        if let Some(dependency_table) = dependency.as_table_like_mut() {
+            // Inherited dependencies `x = { workspace = true }` get their default features from the workspace,
+            // only the additive features can be changed.
+            let inherited = dependency_table
+                .get("workspace")
+                .and_then(|workspace| workspace.as_bool())
+                .unwrap_or(false);
+
+            // Cargo also accepts the 'default_features' spelling.
+            let default_features_key = if dependency_table.contains_key("default_features") {
+                "default_features"
+            } else {
+                "default-features"
+            };
+
+            if inherited {
+                // Keep the 'default-features' tag as defined by the workspace.
+            } else if let Some(default_features) = dependency_table
+                .get_mut(default_features_key)
+                .and_then(|default_features| default_features.as_value_mut())
+            {
+                // Overwrite the current 'default-features' tag with the new one, keeping its formatting.
+                replace_value(default_features, Value::Boolean(Formatted::new(false)));
             } else {
                 // Insert the new 'default-features' tag.
-                dependency_table.insert("default-features", Value::Boolean(Formatted::new(false)));
+                dependency_table.insert(
+                    default_features_key,
+                    Item::Value(Value::Boolean(Formatted::new(false))),
+                );
             };
 
             let current_features_array = dependency_table
                 .get_mut("features")
-                .and_then(|features_array| features_array.as_array_mut());
-
-            // Insert array if non existent crate it.
-            if current_features_array.is_none() {
-                // Only define the array it there are features to add.
-                if !features_to_add.is_empty() {
-                    dependency_table.insert("features", Value::Array(features_to_add));
-                }
+                .and_then(|features_array| features_array.as_value_mut());
 
-                return Ok(());
-            }
+            match current_features_array {
+                // Remove feature array if empty.
+                Some(current_features_array) if features_to_add.is_empty() => {
+                    let suffix = current_features_array.decor().suffix().cloned();
+                    let is_last = dependency_table
+                        .iter()
+                        .last()
+                        .is_some_and(|(key, _)| key == "features");
 
-            let current_features_array_mut = current_features_array.unwrap();
+                    dependency_table.remove("features");
 
-            // Overwrite feature array.
-            *current_features_array_mut = features_to_add; // Safe.
+                    // The whitespace before the closing brace of an inline table is the suffix of its last value.
+                    if is_inline_table && is_last {
+                        let last_value = dependency_table
+                            .iter_mut()
+                            .last()
+                            .and_then(|(_, item)| item.as_value_mut());
 
-            // Remove feature array if empty.
-            if current_features_array_mut.is_empty() {
-                dependency_table.remove("features");
+                        if let (Some(suffix), Some(last_value)) = (suffix, last_value) {
+                            last_value.decor_mut().set_suffix(suffix);
+                        }
+                    }
+                }
+                // Overwrite feature array.
+                Some(current_features_array) => {
+                    replace_value(current_features_array, Value::Array(features_to_add));
+                }
+                // Only define the array it there are features to add.
+                None if !features_to_add.is_empty() => {
+                    dependency_table.insert("features", Item::Value(Value::Array(features_to_add)));
+                }
+                None => {}
             }
         } else {
             return Err(anyhow::anyhow!("The toml document is wrongly formatted."));
@@ -109,20 +188,235 @@ impl TomlEdit {
         Ok(())
     }
 
-    /// Resets the in-memory toml dependencies.
+    /// Resets the in-memory toml dependencies, keeping the changes that were kept with `keep_changes`.
     pub fn reset(&mut self) -> anyhow::Result<()> {
-        let current_deps = self
-            .toml_document
-            .get_mut("dependencies")
-            .ok_or_else(|| anyhow::anyhow!("Dependencies tag not found in toml document"))?;
-
-        *current_deps = self.original_dependencies.clone();
+        self.toml_document = self.baseline_document.clone();
 
         Ok(())
     }
 
+    /// Keeps the current changes, later resets return to the current document.
+    pub fn keep_changes(&mut self) {
+        self.baseline_document = self.toml_document.clone();
+    }
+
+    /// Resets the in-memory toml dependencies to the original document, discarding the kept changes.
+    pub fn reset_original(&mut self) {
+        self.baseline_document = self.original_document.clone();
+        self.toml_document = self.original_document.clone();
+    }
+
     /// Returns a toml-formatted string of the current loaded in-memory toml definition.
     pub fn serialize(&self) -> anyhow::Result<String> {
         Ok(self.toml_document.to_string())
     }
 }
+
+/// Replaces the value while keeping the whitespace before it and a trailing comment.
+/// Trailing whitespace is not kept, it would end up in front of keys inserted after the value in inline tables.
+fn replace_value(value: &mut Value, new_value: Value) {
+    let prefix = value.decor().prefix().cloned();
+    let suffix = value
+        .decor()
+        .suffix()
+        .filter(|suffix| suffix.as_str().is_some_and(|suffix| suffix.contains('#')))
+        .cloned();
+
+    *value = new_value;
+
+    if let Some(prefix) = prefix {
+        value.decor_mut().set_prefix(prefix);
+    }
+    if let Some(suffix) = suffix {
+        value.decor_mut().set_suffix(suffix);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn replaced(toml_contents: &str, dependency: DependencyKey, features: &[&str]) -> String {
+        let mut toml = TomlEdit::new(toml_contents.to_string()).unwrap();
+        toml.replace_dependency_features(
+            &dependency,
+            features.iter().map(|feature| feature.to_string()).collect(),
+        )
+        .unwrap();
+        toml.serialize().unwrap()
+    }
+
+    fn dependency(name: &str) -> DependencyKey {
+        DependencyKey::new(DependencyKind::Normal, None, name)
+    }
+
+    #[test]
+    fn short_notation() {
+        let toml = replaced("[dependencies]\nfoo = \"1\"\n", dependency("foo"), &["a"]);
+
+        assert_eq!(
+            toml,
+            "[dependencies]\nfoo = { features = [\"a\"], default-features = false, version = \"1\" }\n"
+        );
+    }
+
+    #[test]
+    fn inline_table() {
+        let toml = replaced(
+            "[dependencies]\nfoo = { version = \"1\", features = [\"a\", \"b\"] }\n",
+            dependency("foo"),
+            &["a"],
+        );
+
+        assert_eq!(
+            toml,
+            "[dependencies]\nfoo = { version = \"1\", features = [\"a\"], default-features = false }\n"
+        );
+    }
+
+    #[test]
+    fn dependency_table() {
+        let toml = replaced(
+            "[dependencies.foo]\nversion = \"1\"\nfeatures = [\"a\", \"b\"]\n",
+            dependency("foo"),
+            &["b"],
+        );
+
+        assert_eq!(
+            toml,
+            "[dependencies.foo]\nversion = \"1\"\nfeatures = [\"b\"]\ndefault-features = false\n"
+        );
+    }
+
+    #[test]
+    fn dotted_keys() {
+        let toml = replaced(
+            "[dependencies]\nfoo.version = \"1\"\nfoo.features = [\"a\", \"b\"]\n",
+            dependency("foo"),
+            &[],
+        );
+
+        assert_eq!(
+            toml,
+            "[dependencies]\nfoo.version = \"1\"\nfoo.default-features = false\n"
+        );
+    }
+
+    #[test]
+    fn default_features_spelling() {
+        let toml = replaced(
+            "[dependencies]\nfoo = { version = \"1\", default_features = true, features = [\"a\"] }\n",
+            dependency("foo"),
+            &["a"],
+        );
+
+        assert_eq!(
+            toml,
+            "[dependencies]\nfoo = { version = \"1\", default_features = false, features = [\"a\"] }\n"
+        );
+    }
+
+    #[test]
+    fn comments_are_kept() {
+        let toml = replaced(
+            "[dependencies]\n# The runtime.\nfoo = { version = \"1\", features = [\"a\", \"b\"] } # Keep this.\n\n[dependencies.bar]\nversion = \"1\"\nfeatures = [\"a\", \"b\"] # Only a.\n",
+            dependency("bar"),
+            &["a"],
+        );
+
+        assert_eq!(
+            toml,
+            "[dependencies]\n# The runtime.\nfoo = { version = \"1\", features = [\"a\", \"b\"] } # Keep this.\n\n[dependencies.bar]\nversion = \"1\"\nfeatures = [\"a\"] # Only a.\ndefault-features = false\n"
+        );
+    }
+
+    #[test]
+    fn target_dependency_table() {
+        let toml = replaced(
+            "[target.'cfg(unix)'.dependencies.foo]\nversion = \"1\"\nfeatures = [\"a\", \"b\"]\n",
+            DependencyKey::new(DependencyKind::Normal, Some("cfg(unix)"), "foo"),
+            &["a"],
+        );
+
+        assert_eq!(
+            toml,
+            "[target.'cfg(unix)'.dependencies.foo]\nversion = \"1\"\nfeatures = [\"a\"]\ndefault-features = false\n"
+        );
+    }
+
+    #[test]
+    fn workspace_dependency_table() {
+        let toml = replaced(
+            "[workspace]\nmembers = [\"a\"]\n\n[workspace.dependencies.foo]\nversion = \"1\"\nfeatures = [\"a\", \"b\"]\n",
+            DependencyKey::new(DependencyKind::Workspace, None, "foo"),
+            &["b"],
+        );
+
+        assert_eq!(
+            toml,
+            "[workspace]\nmembers = [\"a\"]\n\n[workspace.dependencies.foo]\nversion = \"1\"\nfeatures = [\"b\"]\ndefault-features = false\n"
+        );
+    }
+
+    #[test]
+    fn inline_inherited_dependency() {
+        let toml = replaced(
+            "[dependencies]\ncore = { workspace = true, features = [\"a\"] }\n",
+            dependency("core"),
+            &[],
+        );
+
+        assert_eq!(toml, "[dependencies]\ncore = { workspace = true }\n");
+
+        let toml = replaced(
+            "[dependencies]\ncore = { workspace = true, features = [\"a\", \"b\"] }\n",
+            dependency("core"),
+            &["b"],
+        );
+
+        assert_eq!(
+            toml,
+            "[dependencies]\ncore = { workspace = true, features = [\"b\"] }\n"
+        );
+    }
+
+    #[test]
+    fn inherited_dependency() {
+        let toml = replaced(
+            "[dependencies]\nfoo.workspace = true\nfoo.features = [\"a\", \"b\"]\n",
+            dependency("foo"),
+            &["a"],
+        );
+
+        // The default features are defined by the workspace.
+        assert_eq!(
+            toml,
+            "[dependencies]\nfoo.workspace = true\nfoo.features = [\"a\"]\n"
+        );
+    }
+
+    #[test]
+    fn reset_to_kept_changes() {
+        let mut toml =
+            TomlEdit::new("[dependencies]\nfoo = \"1\"\nbar = \"1\"\n".to_string()).unwrap();
+
+        toml.replace_dependency_features(&dependency("foo"), vec![])
+            .unwrap();
+        toml.keep_changes();
+        toml.replace_dependency_features(&dependency("bar"), vec![])
+            .unwrap();
+        toml.reset().unwrap();
+
+        assert_eq!(
+            toml.serialize().unwrap(),
+            "[dependencies]\nfoo = { default-features = false, version = \"1\" }\nbar = \"1\"\n"
+        );
+
+        toml.reset_original();
+
+        assert_eq!(
+            toml.serialize().unwrap(),
+            "[dependencies]\nfoo = \"1\"\nbar = \"1\"\n"
+        );
+    }
+}
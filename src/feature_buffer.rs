@@ -1,30 +1,94 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+
+use crate::{feature_graph::FeatureActivation, strategy::Search};
 
 /// The feature permutator permutates features and keeps track of successful and unsuccessful removed features.
+///
+/// Features are permutated umbrella first, e.g. `full = ["rt", "net"]` is removed along with `rt` and `net`.
+/// Only if that fails the umbrella is removed on its own and its children are permutated next.
+///
+/// With the ddmin search all features left are removed at once, groups failing to compile are split in halves.
+///
+/// The children of a feature that can not be removed are enabled by it anyway, those are kept without permutating them.
 #[derive(Clone)]
 pub struct DependencyFeaturePermutator {
     pub(crate) original_features: HashSet<String>,
     pub successfully_removed_features: HashSet<String>,
+    /// The features that failed to compile when removed, along with the children those enable.
     pub unsuccessfully_removed_features: HashSet<String>,
+    /// The features left to remove, the next one is at the end.
     tmp_features: Vec<String>,
+    /// The features each feature enables, directly or transitively.
+    children: HashMap<String, HashSet<String>>,
+    /// The umbrella features whose removal along with their children failed, those are removed on their own.
+    descended: HashSet<String>,
+    /// The features removed by the last permutation, the umbrella feature first.
+    removed_features: Vec<String>,
+    search: Search,
+    /// The groups of features left to remove with the ddmin search, the next one is at the end.
+    groups: Vec<Vec<String>>,
 }
 
 impl DependencyFeaturePermutator {
-    pub fn new(features: Vec<String>) -> Self {
+    pub fn new(
+        features: Vec<String>,
+        activations: &HashMap<String, FeatureActivation>,
+        search: Search,
+    ) -> Self {
+        let children = features
+            .iter()
+            .map(|feature| {
+                let children = activations
+                    .get(feature)
+                    .map(|activation| {
+                        activation
+                            .features
+                            .iter()
+                            .filter(|child| *child != feature && features.contains(child))
+                            .cloned()
+                            .collect()
+                    })
+                    .unwrap_or_default();
+
+                (feature.clone(), children)
+            })
+            .collect::<HashMap<String, HashSet<String>>>();
+
+        // An umbrella enables more features than each of its children, thus those are sorted to the end.
+        let mut tmp_features = features.clone();
+        tmp_features.sort_by(|a, b| {
+            children[a]
+                .len()
+                .cmp(&children[b].len())
+                .then_with(|| b.cmp(a))
+        });
+
         DependencyFeaturePermutator {
-            original_features: features.clone().into_iter().collect(),
+            original_features: features.into_iter().collect(),
             successfully_removed_features: HashSet::new(),
             unsuccessfully_removed_features: HashSet::new(),
-            tmp_features: features,
+            tmp_features,
+            children,
+            descended: HashSet::new(),
+            removed_features: Vec::new(),
+            search,
+            groups: Vec::new(),
         }
     }
 
-    /// Removes a feature from the dependency.
+    /// Removes a feature, along with the children only it enables, from the dependency.
     /// Returns the list of current enabled features along with the removed one.
     pub fn permutated_features(&mut self) -> (Vec<String>, String) {
-        let mut features = Vec::new();
+        self.removed_features = match self.search {
+            Search::Linear | Search::Synthesis => {
+                let removed = self.remove_feature();
+                self.removed_group(&removed)
+            }
+            Search::Ddmin => self.next_group(),
+        };
+        let removed = self.removed_features[0].clone();
 
-        let removed = self.remove_feature();
+        let mut features = Vec::new();
 
         // Also iterate over unsuccessfully removed features as those could not be removed.
         for feature in self
@@ -32,7 +96,7 @@ impl DependencyFeaturePermutator {
             .iter()
             .chain(self.unsuccessfully_removed_features.iter())
         {
-            if feature != &removed {
+            if !self.removed_features.contains(feature) {
                 features.push(feature.clone())
             }
         }
@@ -40,6 +104,63 @@ impl DependencyFeaturePermutator {
         (features, removed)
     }
 
+    /// Returns the features removed by the last permutation, the umbrella feature first.
+    pub fn removed_features(&self) -> &[String] {
+        &self.removed_features
+    }
+
+    /// Records that the project compiled without the features of the last permutation.
+    pub fn removal_succeeded(&mut self) {
+        let removed = std::mem::take(&mut self.removed_features);
+
+        self.tmp_features
+            .retain(|feature| !removed.contains(feature));
+        self.successfully_removed_features.extend(removed);
+    }
+
+    /// Records that the project failed to compile without the features of the last permutation.
+    /// Returns `true` if a group of features was removed, the group is then split up and permutated next.
+    pub fn removal_failed(&mut self) -> bool {
+        let mut removed = std::mem::take(&mut self.removed_features);
+
+        match self.search {
+            _ if removed.len() == 1 => {
+                // The children left are implied by the kept feature, removing those would not change the build.
+                let implied = self.children[&removed[0]]
+                    .iter()
+                    .filter(|child| self.tmp_features.contains(child))
+                    .cloned()
+                    .collect::<Vec<String>>();
+                removed.extend(implied);
+
+                self.tmp_features
+                    .retain(|feature| !removed.contains(feature));
+                self.unsuccessfully_removed_features.extend(removed);
+                false
+            }
+            // The umbrella is removed on its own, its children are permutated afterwards.
+            Search::Linear | Search::Synthesis => {
+                self.descended.insert(removed[0].clone());
+                self.tmp_features.push(removed[0].clone());
+                true
+            }
+            Search::Ddmin => {
+                let second_half = removed.split_off(removed.len() / 2);
+                self.groups.push(second_half);
+                self.groups.push(removed);
+                true
+            }
+        }
+    }
+
+    /// Marks the given features as successfully removed, those are not permutated one by one anymore.
+    pub fn remove_features(&mut self, features: &HashSet<String>) {
+        self.tmp_features
+            .retain(|feature| !features.contains(feature));
+        self.successfully_removed_features
+            .extend(features.iter().cloned());
+    }
+
     /// Returns if the features are dependency features left to remove.
     pub fn features_left(&self) -> bool {
         self.tmp_features.is_empty()
@@ -49,9 +170,231 @@ impl DependencyFeaturePermutator {
         self.tmp_features.len() + self.unsuccessfully_removed_features.len()
     }
 
+    /// Returns the number of compiles the search needs at least for the features left.
+    /// The linear search compiles once per feature left, the ddmin search once per pending group.
+    pub fn compiles_left(&self) -> usize {
+        match self.search {
+            Search::Linear | Search::Synthesis => self.tmp_features.len(),
+            Search::Ddmin if self.groups.is_empty() => usize::from(!self.tmp_features.is_empty()),
+            Search::Ddmin => self.groups.len(),
+        }
+    }
+
     /// Removes a feature from the dependency.
     pub fn remove_feature(&mut self) -> String {
         assert!(!self.tmp_features.is_empty());
         self.tmp_features.remove(self.tmp_features.len() - 1)
     }
+
+    /// Returns the next group of features left to remove, all features left if no group is left.
+    /// Umbrella features come before their children and the first half of a split group is removed first,
+    /// thus an umbrella is decided before a child split from it is removed on its own.
+    fn next_group(&mut self) -> Vec<String> {
+        while let Some(mut group) = self.groups.pop() {
+            // Features can be removed in the meantime, e.g. as default features.
+            group.retain(|feature| self.tmp_features.contains(feature));
+
+            if !group.is_empty() {
+                return group;
+            }
+        }
+
+        // Umbrella features first, those are sorted to the end.
+        self.tmp_features.iter().rev().cloned().collect()
+    }
+
+    /// Returns the given feature and the children left to remove that no other remaining feature enables.
+    fn removed_group(&self, feature: &String) -> Vec<String> {
+        let mut group = vec![feature.clone()];
+
+        if self.descended.contains(feature) {
+            return group;
+        }
+
+        let candidates = self.children[feature]
+            .iter()
+            .filter(|child| self.tmp_features.contains(child))
+            .collect::<HashSet<&String>>();
+
+        // Children also enabled by a feature that is kept can not be removed along with the umbrella.
+        let shared = self
+            .tmp_features
+            .iter()
+            .chain(self.unsuccessfully_removed_features.iter())
+            .filter(|other| !candidates.contains(other))
+            .flat_map(|other| self.children[other].iter())
+            .collect::<HashSet<&String>>();
+
+        let mut children = candidates
+            .into_iter()
+            .filter(|child| !shared.contains(child))
+            .cloned()
+            .collect::<Vec<String>>();
+        children.sort();
+
+        group.extend(children);
+        group
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn activations(graph: &[(&str, &[&str])]) -> HashMap<String, FeatureActivation> {
+        graph
+            .iter()
+            .map(|(feature, children)| {
+                let activation = FeatureActivation {
+                    features: children.iter().map(|child| child.to_string()).collect(),
+                    ..FeatureActivation::default()
+                };
+                (feature.to_string(), activation)
+            })
+            .collect()
+    }
+
+    fn permutator(
+        features: &[&str],
+        graph: &[(&str, &[&str])],
+        search: Search,
+    ) -> DependencyFeaturePermutator {
+        DependencyFeaturePermutator::new(
+            features.iter().map(|feature| feature.to_string()).collect(),
+            &activations(graph),
+            search,
+        )
+    }
+
+    /// Permutates all features, a permutation compiles if the features left still enable the required ones.
+    /// Returns the removed and the kept features, sorted, along with the number of compiles.
+    fn permutate(
+        mut permutator: DependencyFeaturePermutator,
+        required: &[&str],
+    ) -> (Vec<String>, Vec<String>, usize) {
+        let mut compiles = 0;
+
+        while !permutator.features_left() {
+            let (features, _) = permutator.permutated_features();
+            compiles += 1;
+
+            let enabled = features
+                .iter()
+                .flat_map(|feature| {
+                    let children = permutator.children[feature].iter().cloned();
+                    children.chain([feature.clone()])
+                })
+                .collect::<HashSet<String>>();
+
+            if required.iter().all(|feature| enabled.contains(*feature)) {
+                permutator.removal_succeeded();
+            } else {
+                permutator.removal_failed();
+            }
+        }
+
+        let mut removed = Vec::from_iter(permutator.successfully_removed_features);
+        removed.sort();
+        let mut kept = Vec::from_iter(permutator.unsuccessfully_removed_features);
+        kept.sort();
+
+        (removed, kept, compiles)
+    }
+
+    const TOKIO: [(&str, &[&str]); 2] = [("full", &["net", "rt"]), ("rt-multi", &["rt"])];
+
+    #[test]
+    fn umbrella_is_removed_first_along_with_its_children() {
+        let mut permutator = permutator(&["net", "full", "rt"], &TOKIO, Search::Linear);
+
+        let (features, removed) = permutator.permutated_features();
+
+        assert_eq!(removed, "full");
+        assert_eq!(permutator.removed_features(), ["full", "net", "rt"]);
+        assert!(features.is_empty());
+    }
+
+    #[test]
+    fn shared_children_are_not_removed_along_with_the_umbrella() {
+        let mut permutator = permutator(&["net", "full", "rt", "rt-multi"], &TOKIO, Search::Linear);
+
+        permutator.permutated_features();
+
+        assert_eq!(permutator.removed_features(), ["full", "net"]);
+    }
+
+    #[test]
+    fn failed_umbrella_descends_into_its_children() {
+        let permutator = permutator(&["net", "full", "rt"], &TOKIO, Search::Linear);
+
+        let (removed, kept, compiles) = permutate(permutator, &["rt"]);
+
+        assert_eq!(removed, ["full", "net"]);
+        assert_eq!(kept, ["rt"]);
+        // The group, the umbrella, and each child.
+        assert_eq!(compiles, 4);
+    }
+
+    #[test]
+    fn children_of_a_required_umbrella_are_implied() {
+        for search in [Search::Linear, Search::Ddmin] {
+            let permutator = permutator(&["net", "full", "rt", "time"], &TOKIO, search);
+
+            let (removed, kept, _) = permutate(permutator, &["full"]);
+
+            assert_eq!(removed, ["time"]);
+            assert_eq!(kept, ["full", "net", "rt"]);
+        }
+    }
+
+    #[test]
+    fn ddmin_removes_all_features_at_once() {
+        let permutator = permutator(&["net", "full", "rt", "time"], &TOKIO, Search::Ddmin);
+
+        let (removed, kept, compiles) = permutate(permutator, &[]);
+
+        assert_eq!(removed, ["full", "net", "rt", "time"]);
+        assert!(kept.is_empty());
+        assert_eq!(compiles, 1);
+    }
+
+    #[test]
+    fn ddmin_splits_failing_groups() {
+        let permutator = permutator(&["a", "b", "c", "d"], &[], Search::Ddmin);
+
+        let (removed, kept, compiles) = permutate(permutator, &["d"]);
+
+        assert_eq!(removed, ["a", "b", "c"]);
+        assert_eq!(kept, ["d"]);
+        // All features, each half, and each feature of the half with `d`.
+        assert_eq!(compiles, 5);
+    }
+
+    #[test]
+    fn compiles_left() {
+        let mut linear = permutator(&["a", "b", "c"], &[], Search::Linear);
+        assert_eq!(linear.compiles_left(), 3);
+        linear.permutated_features();
+        linear.removal_succeeded();
+        assert_eq!(linear.compiles_left(), 2);
+
+        let mut ddmin = permutator(&["a", "b", "c"], &[], Search::Ddmin);
+        assert_eq!(ddmin.compiles_left(), 1);
+        ddmin.permutated_features();
+        ddmin.removal_failed();
+        assert_eq!(ddmin.compiles_left(), 2);
+    }
+
+    #[test]
+    fn removed_features_are_not_permutated() {
+        let mut permutator = permutator(&["net", "full", "rt"], &TOKIO, Search::Linear);
+
+        permutator.remove_features(&HashSet::from(["full".to_string(), "net".to_string()]));
+
+        let (removed, kept, compiles) = permutate(permutator, &["rt"]);
+
+        assert_eq!(removed, ["full", "net"]);
+        assert_eq!(kept, ["rt"]);
+        assert_eq!(compiles, 1);
+    }
 }
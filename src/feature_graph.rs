@@ -0,0 +1,308 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use cargo_metadata::Package;
+use serde::{Deserialize, Serialize};
+
+/// A single value of a `[features]` definition.
+enum FeatureValue<'a> {
+    /// `feature`, enables another feature of the same crate.
+    Feature(&'a str),
+    /// `dep:crate`, enables an optional dependency.
+    Dependency(&'a str),
+    /// `crate/feature` or the weak `crate?/feature`, enables a feature of a dependency.
+    /// The weak variant does not enable the dependency itself if it is optional.
+    DependencyFeature {
+        dependency: &'a str,
+        feature: &'a str,
+        weak: bool,
+    },
+}
+
+impl<'a> FeatureValue<'a> {
+    fn parse(value: &'a str) -> Self {
+        if let Some(dependency) = value.strip_prefix("dep:") {
+            return FeatureValue::Dependency(dependency);
+        }
+
+        match value.split_once('/') {
+            Some((dependency, feature)) => match dependency.strip_suffix('?') {
+                Some(dependency) => FeatureValue::DependencyFeature {
+                    dependency,
+                    feature,
+                    weak: true,
+                },
+                None => FeatureValue::DependencyFeature {
+                    dependency,
+                    feature,
+                    weak: false,
+                },
+            },
+            None => FeatureValue::Feature(value),
+        }
+    }
+}
+
+/// Returns if the given `[features]` value references a feature of a dependency, e.g. `tokio/net` or `tokio?/net`.
+pub fn is_dependency_feature_reference(value: &str) -> bool {
+    matches!(
+        FeatureValue::parse(value),
+        FeatureValue::DependencyFeature { .. }
+    )
+}
+
+/// Returns the dependency name of a `dep/feature` or `dep?/feature` reference.
+pub fn referenced_dependency(value: &str) -> Option<&str> {
+    match FeatureValue::parse(value) {
+        FeatureValue::DependencyFeature { dependency, .. } => Some(dependency),
+        _ => None,
+    }
+}
+
+/// What enabling a feature activates, following the feature graph of the package transitively.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct FeatureActivation {
+    /// The other features of the package that are enabled.
+    pub features: HashSet<String>,
+    /// The optional dependencies of the package that are enabled.
+    pub optional_dependencies: HashSet<String>,
+    /// The features of the dependencies of the package that are enabled, e.g. `tokio/net`.
+    /// Weak references are kept in their `tokio?/net` form as they only apply if the dependency is enabled otherwise.
+    pub dependency_features: HashSet<String>,
+}
+
+impl FeatureActivation {
+    pub fn is_empty(&self) -> bool {
+        self.features.is_empty()
+            && self.optional_dependencies.is_empty()
+            && self.dependency_features.is_empty()
+    }
+}
+
+/// The `[features]` definitions of a package.
+pub struct FeatureGraph<'a> {
+    package: &'a Package,
+}
+
+impl<'a> FeatureGraph<'a> {
+    pub fn new(package: &'a Package) -> Self {
+        Self { package }
+    }
+
+    /// Returns if the given feature is declared by the package.
+    pub fn contains(&self, feature: &str) -> bool {
+        self.package.features.contains_key(feature)
+    }
+
+    /// Returns everything the given features activate, the given features themselves are not included.
+    pub fn activation<'f>(&self, features: impl IntoIterator<Item = &'f str>) -> FeatureActivation {
+        let mut activation = FeatureActivation::default();
+        let mut visited = HashSet::new();
+        let features = Vec::from_iter(features);
+        let mut queue = VecDeque::from_iter(features.iter().copied());
+
+        while let Some(feature) = queue.pop_front() {
+            if !visited.insert(feature) {
+                continue;
+            }
+
+            let values = match self.package.features.get(feature) {
+                Some(values) => values,
+                None => continue,
+            };
+
+            for value in values {
+                match FeatureValue::parse(value) {
+                    FeatureValue::Feature(feature) => {
+                        if self.contains(feature) {
+                            activation.features.insert(feature.to_string());
+                            queue.push_back(feature);
+                        }
+                    }
+                    FeatureValue::Dependency(dependency) => {
+                        activation
+                            .optional_dependencies
+                            .insert(dependency.to_string());
+                    }
+                    FeatureValue::DependencyFeature {
+                        dependency,
+                        feature,
+                        weak,
+                    } => {
+                        if weak {
+                            activation
+                                .dependency_features
+                                .insert(format!("{dependency}?/{feature}"));
+                            continue;
+                        }
+
+                        activation
+                            .dependency_features
+                            .insert(format!("{dependency}/{feature}"));
+
+                        if self.is_optional_dependency(dependency) {
+                            activation
+                                .optional_dependencies
+                                .insert(dependency.to_string());
+                        }
+                    }
+                }
+            }
+        }
+
+        // Cyclic definitions lead back to the given features.
+        activation
+            .features
+            .retain(|feature| !features.contains(&feature.as_str()));
+
+        activation
+    }
+
+    /// Returns the activations of each of the given features on its own.
+    pub fn activations<'f>(
+        &self,
+        features: impl IntoIterator<Item = &'f String>,
+    ) -> HashMap<String, FeatureActivation> {
+        features
+            .into_iter()
+            .map(|feature| (feature.clone(), self.activation([feature.as_str()])))
+            .filter(|(_, activation)| !activation.is_empty())
+            .collect()
+    }
+
+    fn is_optional_dependency(&self, name: &str) -> bool {
+        self.package.dependencies.iter().any(|dependency| {
+            dependency.optional && dependency.rename.as_ref().unwrap_or(&dependency.name) == name
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn package(features: serde_json::Value) -> Package {
+        serde_json::from_value(serde_json::json!({
+            "name": "tokio",
+            "version": "1.0.0",
+            "id": "tokio 1.0.0 (registry+https://github.com/rust-lang/crates.io-index)",
+            "dependencies": [
+                {
+                    "name": "bytes",
+                    "req": "^1",
+                    "kind": null,
+                    "optional": true,
+                    "uses_default_features": true,
+                    "features": [],
+                    "target": null,
+                    "rename": null,
+                    "registry": null,
+                    "source": null
+                },
+                {
+                    "name": "libc",
+                    "req": "^0.2",
+                    "kind": null,
+                    "optional": false,
+                    "uses_default_features": true,
+                    "features": [],
+                    "target": null,
+                    "rename": null,
+                    "registry": null,
+                    "source": null
+                }
+            ],
+            "features": features,
+            "targets": [],
+            "manifest_path": "/tokio/Cargo.toml",
+        }))
+        .unwrap()
+    }
+
+    fn set(values: &[&str]) -> HashSet<String> {
+        values.iter().map(|value| value.to_string()).collect()
+    }
+
+    #[test]
+    fn nested_features_are_followed() {
+        let package = package(serde_json::json!({
+            "full": ["net", "rt-multi"],
+            "net": [],
+            "rt": [],
+            "rt-multi": ["rt"],
+        }));
+
+        let activation = FeatureGraph::new(&package).activation(["full"]);
+
+        assert_eq!(activation.features, set(&["net", "rt-multi", "rt"]));
+        assert!(activation.optional_dependencies.is_empty());
+    }
+
+    #[test]
+    fn cyclic_features_terminate() {
+        let package = package(serde_json::json!({
+            "a": ["b"],
+            "b": ["a"],
+        }));
+
+        let activation = FeatureGraph::new(&package).activation(["a"]);
+
+        assert_eq!(activation.features, set(&["b"]));
+    }
+
+    #[test]
+    fn dependency_references() {
+        let package = package(serde_json::json!({
+            "bytes": ["dep:bytes"],
+            "io": ["bytes/std", "libc/extra_traits"],
+        }));
+        let graph = FeatureGraph::new(&package);
+
+        let activation = graph.activation(["bytes"]);
+        assert_eq!(activation.optional_dependencies, set(&["bytes"]));
+        assert!(activation.dependency_features.is_empty());
+
+        // A strong reference enables an optional dependency as well.
+        let activation = graph.activation(["io"]);
+        assert_eq!(
+            activation.dependency_features,
+            set(&["bytes/std", "libc/extra_traits"])
+        );
+        assert_eq!(activation.optional_dependencies, set(&["bytes"]));
+    }
+
+    #[test]
+    fn weak_dependency_references() {
+        let package = package(serde_json::json!({
+            "serde": ["bytes?/serde"],
+        }));
+
+        let activation = FeatureGraph::new(&package).activation(["serde"]);
+
+        assert_eq!(activation.dependency_features, set(&["bytes?/serde"]));
+        assert!(activation.optional_dependencies.is_empty());
+    }
+
+    #[test]
+    fn activations_without_effect_are_left_out() {
+        let package = package(serde_json::json!({
+            "full": ["net"],
+            "net": [],
+        }));
+        let features = vec!["full".to_string(), "net".to_string()];
+
+        let activations = FeatureGraph::new(&package).activations(&features);
+
+        assert_eq!(Vec::from_iter(activations.keys()), ["full"]);
+    }
+
+    #[test]
+    fn dependency_feature_references() {
+        assert!(is_dependency_feature_reference("tokio/net"));
+        assert!(is_dependency_feature_reference("tokio?/net"));
+        assert!(!is_dependency_feature_reference("dep:tokio"));
+        assert!(!is_dependency_feature_reference("net"));
+
+        assert_eq!(referenced_dependency("tokio?/net"), Some("tokio"));
+        assert_eq!(referenced_dependency("dep:tokio"), None);
+    }
+}
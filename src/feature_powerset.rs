@@ -0,0 +1,83 @@
+/// Returns the combinations of the given features, ordered by their size.
+/// The empty combination is always the first one, combinations larger than `depth` are omitted.
+pub fn feature_powerset(features: &[String], depth: Option<usize>) -> Vec<Vec<String>> {
+    let depth = depth.unwrap_or(features.len()).min(features.len());
+
+    let mut combinations = vec![vec![]];
+    // The combinations of the previous size, with the index of their last feature.
+    let mut previous: Vec<(Vec<String>, usize)> = vec![(vec![], 0)];
+
+    for _ in 0..depth {
+        let mut next = Vec::new();
+
+        for (combination, start) in &previous {
+            for (index, feature) in features.iter().enumerate().skip(*start) {
+                let mut combination = combination.clone();
+                combination.push(feature.clone());
+                next.push((combination, index + 1));
+            }
+        }
+
+        combinations.extend(next.iter().map(|(combination, _)| combination.clone()));
+        previous = next;
+    }
+
+    combinations
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn features(features: &[&str]) -> Vec<String> {
+        features.iter().map(|feature| feature.to_string()).collect()
+    }
+
+    #[test]
+    fn combinations_are_ordered_by_size() {
+        let combinations = feature_powerset(&features(&["a", "b", "c"]), None);
+
+        assert_eq!(
+            combinations,
+            [
+                features(&[]),
+                features(&["a"]),
+                features(&["b"]),
+                features(&["c"]),
+                features(&["a", "b"]),
+                features(&["a", "c"]),
+                features(&["b", "c"]),
+                features(&["a", "b", "c"]),
+            ]
+        );
+    }
+
+    #[test]
+    fn depth_limits_the_combination_size() {
+        let combinations = feature_powerset(&features(&["a", "b", "c"]), Some(1));
+
+        assert_eq!(
+            combinations,
+            [
+                features(&[]),
+                features(&["a"]),
+                features(&["b"]),
+                features(&["c"])
+            ]
+        );
+    }
+
+    #[test]
+    fn depth_beyond_the_feature_count() {
+        assert_eq!(feature_powerset(&features(&["a", "b"]), Some(5)).len(), 4);
+        assert_eq!(
+            feature_powerset(&features(&["a", "b"]), Some(0)),
+            [features(&[])]
+        );
+    }
+
+    #[test]
+    fn no_features() {
+        assert_eq!(feature_powerset(&[], None), [features(&[])]);
+    }
+}
@@ -0,0 +1,301 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+};
+
+use cargo_metadata::Package;
+
+use crate::own_features::{cfg_features, is_identifier_char, rust_sources};
+
+/// Keywords that declare an item, the identifier following those is the name of the item.
+const ITEM_KEYWORDS: [&str; 10] = [
+    "mod",
+    "fn",
+    "struct",
+    "enum",
+    "union",
+    "trait",
+    "type",
+    "const",
+    "static",
+    "macro_rules",
+];
+
+/// Identifiers in use declarations that are not the name of an item.
+const PATH_KEYWORDS: [&str; 7] = ["pub", "use", "crate", "self", "super", "as", "in"];
+
+/// The items declared in the sources of a dependency, by name, along with the features gating them.
+///
+/// This is a heuristic, the sources are scanned line by line for `#[cfg(feature = "...")]` attributes
+/// and the items following those. Items in gated `impl`, `mod` and `trait` blocks are gated as well.
+pub struct GatedItems {
+    items: HashMap<String, HashSet<String>>,
+}
+
+impl GatedItems {
+    /// Scans the sources of the given package for items gated behind features.
+    pub fn new(package: &Package) -> anyhow::Result<Self> {
+        let mut items = HashMap::new();
+
+        if let Some(crate_directory) = package.manifest_path.parent() {
+            let mut sources = Vec::new();
+            rust_sources(crate_directory.as_std_path(), true, &mut sources)?;
+
+            for source in sources {
+                let contents = fs::read_to_string(&source)?;
+                gated_items(&contents, &mut items);
+            }
+        }
+
+        Ok(Self { items })
+    }
+
+    /// Returns the features that gate the items the given compiler output refers to.
+    pub fn features_for(&self, compiler_output: &str) -> HashSet<String> {
+        let mut features = HashSet::new();
+
+        for message in error_messages(compiler_output) {
+            // The compiler points out items that are configured out, e.g. 'the item is gated behind the `fs` feature'.
+            if let Some(index) = message.find("gated behind the `") {
+                let rest = &message[index + "gated behind the `".len()..];
+                if let Some(end) = rest.find('`') {
+                    features.insert(rest[..end].to_string());
+                }
+            }
+
+            for identifier in quoted_identifiers(message) {
+                if let Some(gating_features) = self.items.get(identifier) {
+                    features.extend(gating_features.iter().cloned());
+                }
+            }
+        }
+
+        features
+    }
+}
+
+/// Records the items of the given source that are gated behind features.
+fn gated_items(source: &str, items: &mut HashMap<String, HashSet<String>>) {
+    // The features gating the whole file, `#![cfg(feature = "...")]`.
+    let mut file_features = HashSet::new();
+    // The features of the attributes preceding the next item.
+    let mut pending_features = HashSet::new();
+    // The features of the gated blocks the current line is in, along with the depth they were opened at
+    // and if those are use declarations spanning multiple lines.
+    let mut scopes: Vec<(i32, HashSet<String>, bool)> = Vec::new();
+    let mut depth = 0;
+
+    for line in source.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with("//") {
+            continue;
+        }
+
+        if line.starts_with("#![") {
+            cfg_features(line, &mut file_features);
+            continue;
+        }
+
+        if line.starts_with("#[") {
+            cfg_features(line, &mut pending_features);
+            continue;
+        }
+
+        let mut features = file_features.clone();
+        features.extend(pending_features.iter().cloned());
+        for (_, scope_features, _) in &scopes {
+            features.extend(scope_features.iter().cloned());
+        }
+
+        let in_use_declaration = scopes.last().is_some_and(|(_, _, is_use)| *is_use);
+
+        if !features.is_empty() {
+            for identifier in declared_identifiers(line, in_use_declaration) {
+                items
+                    .entry(identifier.to_string())
+                    .or_default()
+                    .extend(features.iter().cloned());
+            }
+        }
+
+        let opened = line.matches('{').count() as i32 - line.matches('}').count() as i32;
+        if opened > 0 && !pending_features.is_empty() {
+            scopes.push((depth, pending_features.clone(), is_use_declaration(line)));
+        }
+
+        depth += opened;
+        scopes.retain(|(scope_depth, _, _)| depth > *scope_depth);
+        pending_features.clear();
+    }
+}
+
+/// Returns the names of the items declared on the given line.
+/// For use declarations all identifiers are returned, those can be re-exported.
+fn declared_identifiers(line: &str, in_use_declaration: bool) -> Vec<&str> {
+    let identifiers = identifiers(line);
+
+    if in_use_declaration || is_use_declaration(line) {
+        return identifiers
+            .into_iter()
+            .filter(|identifier| !PATH_KEYWORDS.contains(identifier))
+            .collect();
+    }
+
+    identifiers
+        .windows(2)
+        .filter(|pair| ITEM_KEYWORDS.contains(&pair[0]))
+        .map(|pair| pair[1])
+        .take(1)
+        .collect()
+}
+
+/// Returns if the given line starts a use declaration, e.g. `pub(crate) use crate::item::{`.
+fn is_use_declaration(line: &str) -> bool {
+    let identifiers = identifiers(line);
+
+    identifiers.contains(&"use")
+        && identifiers
+            .iter()
+            .take_while(|identifier| **identifier != "use")
+            .all(|identifier| *identifier == "pub" || *identifier == "crate")
+}
+
+fn identifiers(line: &str) -> Vec<&str> {
+    line.split(|char: char| !is_identifier_char(char))
+        .filter(|identifier| !identifier.is_empty())
+        .collect()
+}
+
+/// Returns the lines of the error diagnostics in the compiler output, warnings are skipped.
+fn error_messages(compiler_output: &str) -> Vec<&str> {
+    let mut messages = Vec::new();
+    let mut in_error = false;
+
+    for line in compiler_output.lines() {
+        if line.starts_with("error") {
+            in_error = true;
+        } else if line.starts_with("warning") {
+            in_error = false;
+        }
+
+        let line = line.trim_start();
+        let is_message = line.starts_with("error")
+            || line.starts_with("note")
+            || line.starts_with("help")
+            || line.starts_with("= note")
+            || line.starts_with("= help");
+
+        if in_error && is_message {
+            messages.push(line);
+        }
+    }
+
+    messages
+}
+
+/// Returns the identifiers of the paths quoted with backticks in the given message, e.g. `tokio::fs::File`.
+fn quoted_identifiers(message: &str) -> Vec<&str> {
+    message
+        .split('`')
+        .skip(1)
+        .step_by(2)
+        .flat_map(|quoted| quoted.split(|char: char| !is_identifier_char(char)))
+        .filter(|identifier| !identifier.is_empty())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = r#"
+        #[cfg(feature = "fs")]
+        pub mod fs {
+            pub struct File;
+            pub fn read() {}
+        }
+
+        #[cfg(feature = "fs")]
+        pub use crate::fs::File;
+
+        #[cfg(feature = "net")]
+        pub use crate::net::{
+            TcpListener,
+            TcpStream as Stream,
+        };
+
+        #[cfg(all(feature = "rt", feature = "macros"))]
+        #[macro_export]
+        macro_rules! main {}
+
+        pub struct Runtime;
+    "#;
+
+    fn gated_items_of(source: &str) -> GatedItems {
+        let mut items = HashMap::new();
+        gated_items(source, &mut items);
+
+        GatedItems { items }
+    }
+
+    fn sorted(features: HashSet<String>) -> Vec<String> {
+        let mut features = Vec::from_iter(features);
+        features.sort();
+        features
+    }
+
+    #[test]
+    fn gated_items_and_blocks() {
+        let gated_items = gated_items_of(SOURCE);
+
+        assert_eq!(gated_items.items["fs"], HashSet::from(["fs".to_string()]));
+        assert_eq!(gated_items.items["File"], HashSet::from(["fs".to_string()]));
+        assert_eq!(gated_items.items["read"], HashSet::from(["fs".to_string()]));
+        assert_eq!(sorted(gated_items.items["main"].clone()), ["macros", "rt"]);
+        assert!(!gated_items.items.contains_key("Runtime"));
+    }
+
+    #[test]
+    fn gated_use_declarations() {
+        let gated_items = gated_items_of(SOURCE);
+
+        for item in ["TcpListener", "TcpStream", "Stream"] {
+            assert_eq!(gated_items.items[item], HashSet::from(["net".to_string()]));
+        }
+        assert!(!gated_items.items.contains_key("crate"));
+    }
+
+    #[test]
+    fn features_pointed_to_by_the_compiler() {
+        let gated_items = gated_items_of(SOURCE);
+        let compiler_output = r#"
+warning: unused import: `crate::fs::read`
+error[E0432]: unresolved import `tokio::fs`
+  |
+  = note: the item is gated behind the `io-util` feature
+error[E0433]: failed to resolve: could not find `TcpStream` in `net`
+  |
+help: consider importing this struct
+"#;
+
+        assert_eq!(
+            sorted(gated_items.features_for(compiler_output)),
+            ["fs", "io-util", "net"]
+        );
+    }
+
+    #[test]
+    fn warnings_point_to_no_feature() {
+        let gated_items = gated_items_of(SOURCE);
+        let compiler_output = "warning: unused import: `File`\n  |\n  = note: `#[warn(unused_imports)]` on by default\n";
+
+        assert!(gated_items.features_for(compiler_output).is_empty());
+    }
+
+    #[test]
+    fn no_feature_is_synthesized_for_ungated_items() {
+        let gated_items = gated_items_of(SOURCE);
+        let compiler_output = "error[E0308]: mismatched types, expected `Runtime`, found `u32`\n";
+
+        assert!(gated_items.features_for(compiler_output).is_empty());
+    }
+}
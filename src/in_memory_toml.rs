@@ -1,13 +1,13 @@
 use std::ops::Deref;
 
-use cargo_toml::{DependencyDetail, DepsSet, Manifest};
-use serde::Serialize;
+use cargo_toml::{DepsSet, Manifest};
+
+use crate::{dependency_kind::DependencyKind, feature_graph::is_dependency_feature_reference};
 
 /// In memory representation of a Cargo.toml file.
-/// This can be edited and serialized but it will not keep original formatting.
+/// This is only read, the permutations are applied to the [`crate::TomlEdit`] document which keeps the original formatting.
 pub struct TomlInMemory {
     pub manifest: Manifest,
-    pub original_dependencies: DepsSet,
 }
 
 impl TomlInMemory {
@@ -15,59 +15,66 @@ impl TomlInMemory {
     pub fn new(toml_contents: String) -> anyhow::Result<Self> {
         let manifest = Manifest::from_str(&toml_contents)?;
 
-        Ok(Self {
-            original_dependencies: manifest.dependencies.clone(),
-            manifest,
-        })
+        Ok(Self { manifest })
     }
 
-    /// Serializes the in memory toml to a toml formatted string.
-    pub fn serialize(&self) -> anyhow::Result<String> {
-        let mut toml_buffer = String::new();
-        let serializer = toml::ser::Serializer::new(&mut toml_buffer);
-        self.manifest.serialize(serializer)?;
-        Ok(toml_buffer)
-    }
+    /// Returns all dependency tables with their kind and, for `[target.'...'.dependencies]` tables, their platform.
+    pub fn dependency_tables(&self) -> Vec<(DependencyKind, Option<&str>, &DepsSet)> {
+        let mut tables = vec![
+            (DependencyKind::Normal, None, &self.manifest.dependencies),
+            (
+                DependencyKind::Development,
+                None,
+                &self.manifest.dev_dependencies,
+            ),
+            (
+                DependencyKind::Build,
+                None,
+                &self.manifest.build_dependencies,
+            ),
+        ];
 
-    /// Replaces the dependency features with the given features.
-    pub fn replace_dependency_feature(
-        &mut self,
-        dependency: &String,
-        features: Vec<String>,
-    ) -> anyhow::Result<()> {
-        let dependency = self
-            .manifest
-            .dependencies
-            .get_mut(dependency)
-            .ok_or_else(|| anyhow::anyhow!("Dependency not found"))?;
+        for (target, dependencies) in &self.manifest.target {
+            let target = Some(target.as_str());
+            tables.push((DependencyKind::Normal, target, &dependencies.dependencies));
+            tables.push((
+                DependencyKind::Development,
+                target,
+                &dependencies.dev_dependencies,
+            ));
+            tables.push((
+                DependencyKind::Build,
+                target,
+                &dependencies.build_dependencies,
+            ));
+        }
 
-        match dependency {
-            // Short dependency notation `x = "1.0"`
-            cargo_toml::Dependency::Simple(version) => {
-                let new_dependency = DependencyDetail {
-                    version: Some(version.clone()),
-                    features,
-                    default_features: false,
-                    ..Default::default()
-                };
-                *dependency = cargo_toml::Dependency::Detailed(new_dependency);
-            }
-            // Detailed dependency notation `x = { version = "1.0", features = ["a", "b"] }`
-            cargo_toml::Dependency::Detailed(detailed) => {
-                detailed.features = features;
-                detailed.default_features = false;
-            }
-            cargo_toml::Dependency::Inherited(_inherited) => {}
-        };
+        tables
+    }
 
-        Ok(())
+    /// Returns the `[workspace.dependencies]` table if this is a workspace root.
+    pub fn workspace_dependency_table(&self) -> Option<&DepsSet> {
+        self.manifest
+            .workspace
+            .as_ref()
+            .map(|workspace| &workspace.dependencies)
     }
 
-    /// Resets the dependencies of this in memory toml definition.
-    /// This does not update any files.
-    pub fn reset_dependencies(&mut self) -> anyhow::Result<()> {
-        self.manifest.dependencies = self.original_dependencies.clone();
-        Ok(())
+    /// Returns the `dep/feature` references of each feature in the `[features]` table.
+    pub fn feature_dependency_references(&self) -> Vec<(&str, Vec<&str>)> {
+        self.manifest
+            .features
+            .iter()
+            .map(|(feature, values)| {
+                let references = values
+                    .iter()
+                    .map(String::as_str)
+                    .filter(|value| is_dependency_feature_reference(value))
+                    .collect::<Vec<&str>>();
+                (feature.as_str(), references)
+            })
+            .filter(|(_, references)| !references.is_empty())
+            .collect()
     }
 }
 
@@ -3,9 +3,16 @@ use subcommands::Cargo;
 
 mod cargo_project;
 mod create_dependencies;
+mod dependency_kind;
 mod editable_toml;
 mod feature_buffer;
+mod feature_graph;
+mod feature_powerset;
+mod feature_synthesis;
 mod in_memory_toml;
+mod own_features;
+mod platform;
+mod strategy;
 mod subcommands;
 
 pub(crate) mod report;
@@ -0,0 +1,303 @@
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use cargo_metadata::Package;
+
+use crate::{feature_graph::FeatureGraph, report::OwnFeatures};
+
+/// Cross-references the `[features]` table of the package with the features referenced by its sources.
+///
+/// A declared feature is used if the sources refer to it, or if it activates an optional dependency,
+/// a feature of a dependency or another used feature, like umbrella features `full = ["a", "b"]` do.
+pub fn analyze_own_features(
+    package: &Package,
+    crate_directory: &Path,
+) -> anyhow::Result<OwnFeatures> {
+    let referenced_features = referenced_features(crate_directory)?;
+    let feature_graph = FeatureGraph::new(package);
+
+    let mut unused_features = package
+        .features
+        .keys()
+        .filter(|feature| feature.as_str() != "default")
+        .filter(|feature| !referenced_features.contains(*feature))
+        .filter(|feature| {
+            let activation = feature_graph.activation([feature.as_str()]);
+
+            activation.optional_dependencies.is_empty()
+                && activation.dependency_features.is_empty()
+                && activation.features.is_disjoint(&referenced_features)
+        })
+        .cloned()
+        .collect::<Vec<String>>();
+
+    let mut undeclared_features = referenced_features
+        .into_iter()
+        .filter(|feature| !feature_graph.contains(feature))
+        .collect::<Vec<String>>();
+
+    unused_features.sort();
+    undeclared_features.sort();
+
+    Ok(OwnFeatures {
+        unused_features,
+        undeclared_features,
+    })
+}
+
+/// Returns the features referenced by `cfg(feature = "...")`, `cfg_attr(...)` and `cfg!(...)` in the rust sources of the crate.
+fn referenced_features(crate_directory: &Path) -> anyhow::Result<HashSet<String>> {
+    let mut sources = Vec::new();
+    rust_sources(crate_directory, true, &mut sources)?;
+
+    let mut features = HashSet::new();
+
+    for source in sources {
+        let contents = fs::read_to_string(&source)?;
+        cfg_features(&contents, &mut features);
+    }
+
+    Ok(features)
+}
+
+/// Collects the rust source files in the given directory.
+/// Build output, hidden directories and nested packages, like workspace members, are skipped.
+pub(crate) fn rust_sources(
+    directory: &Path,
+    is_root: bool,
+    sources: &mut Vec<PathBuf>,
+) -> anyhow::Result<()> {
+    if !is_root && directory.join("Cargo.toml").exists() {
+        return Ok(());
+    }
+
+    for entry in fs::read_dir(directory)? {
+        let path = entry?.path();
+        let file_name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+
+        if path.is_dir() {
+            if file_name != "target" && !file_name.starts_with('.') {
+                rust_sources(&path, false, sources)?;
+            }
+        } else if path.extension().is_some_and(|extension| extension == "rs") {
+            sources.push(path);
+        }
+    }
+
+    Ok(())
+}
+
+/// Collects the `feature = "..."` predicates of the `cfg` expressions in the given source.
+pub(crate) fn cfg_features(source: &str, features: &mut HashSet<String>) {
+    // Commented out code does not count.
+    let source = source
+        .lines()
+        .filter(|line| !line.trim_start().starts_with("//"))
+        .collect::<Vec<&str>>()
+        .join("\n");
+
+    let mut rest = source.as_str();
+
+    while let Some(index) = rest.find("cfg") {
+        let preceded_by_identifier = rest[..index].chars().last().is_some_and(is_identifier_char);
+        rest = &rest[index + "cfg".len()..];
+
+        if preceded_by_identifier {
+            continue;
+        }
+
+        let expression = rest
+            .strip_prefix("_attr")
+            .unwrap_or(rest)
+            .trim_start_matches('!')
+            .trim_start();
+
+        if let Some(expression) = expression.strip_prefix('(') {
+            feature_predicates(parenthesized(expression), features);
+        }
+    }
+}
+
+/// Returns the contents up to the closing parenthesis, skipping string literals.
+fn parenthesized(expression: &str) -> &str {
+    let mut depth = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+
+    for (index, char) in expression.char_indices() {
+        match char {
+            _ if escaped => escaped = false,
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            '(' if !in_string => depth += 1,
+            ')' if !in_string => {
+                if depth == 0 {
+                    return &expression[..index];
+                }
+                depth -= 1;
+            }
+            _ => {}
+        }
+    }
+
+    expression
+}
+
+/// Collects the `feature = "..."` predicates in the given `cfg` expression.
+/// Other predicates ending in `feature`, like `target_feature = "..."`, are ignored.
+fn feature_predicates(expression: &str, features: &mut HashSet<String>) {
+    let mut rest = expression;
+
+    while let Some(index) = rest.find("feature") {
+        let preceded_by_identifier = rest[..index].chars().last().is_some_and(is_identifier_char);
+        rest = &rest[index + "feature".len()..];
+
+        if preceded_by_identifier {
+            continue;
+        }
+
+        let value = match rest.trim_start().strip_prefix('=') {
+            Some(value) => value.trim_start(),
+            None => continue,
+        };
+
+        if let Some(value) = value.strip_prefix('"') {
+            if let Some(end) = value.find('"') {
+                features.insert(value[..end].to_string());
+            }
+        }
+    }
+}
+
+pub(crate) fn is_identifier_char(char: char) -> bool {
+    char.is_alphanumeric() || char == '_'
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cfg_features_of(source: &str) -> Vec<String> {
+        let mut features = HashSet::new();
+        cfg_features(source, &mut features);
+
+        let mut features = Vec::from_iter(features);
+        features.sort();
+        features
+    }
+
+    #[test]
+    fn cfg_attributes_and_macros() {
+        let source = r#"
+            #![cfg(feature = "std")]
+            #[cfg(all(feature = "net", not(feature = "wasm")))]
+            mod net;
+            #[cfg_attr(feature = "serde", derive(Serialize))]
+            struct Config;
+            fn main() {
+                if cfg!(feature="tracing") {}
+            }
+        "#;
+
+        assert_eq!(
+            cfg_features_of(source),
+            ["net", "serde", "std", "tracing", "wasm"]
+        );
+    }
+
+    #[test]
+    fn other_predicates_are_ignored() {
+        let source = r#"
+            #[cfg(target_feature = "avx2")]
+            fn simd() {}
+            #[cfg(any(target_os = "linux", feature = "io"))]
+            fn io() {}
+            fn config() { let feature = "fs"; }
+        "#;
+
+        assert_eq!(cfg_features_of(source), ["io"]);
+    }
+
+    #[test]
+    fn commented_out_code_is_ignored() {
+        let source = r#"
+            // #[cfg(feature = "old")]
+            /// Enabled with `#[cfg(feature = "doc")]`.
+            #[cfg(feature = "new")]
+            fn new() {}
+        "#;
+
+        assert_eq!(cfg_features_of(source), ["new"]);
+    }
+
+    #[test]
+    fn parentheses_in_string_literals() {
+        let source = r#"#[cfg(all(feature = "a(", feature = "b"))] fn f() {}"#;
+
+        assert_eq!(cfg_features_of(source), ["a(", "b"]);
+    }
+
+    #[test]
+    fn identifiers_ending_in_cfg() {
+        let source = r#"fn build_cfg(feature = "x") {} #[cfg(feature = "y")] fn y() {}"#;
+
+        assert_eq!(cfg_features_of(source), ["y"]);
+    }
+
+    #[test]
+    fn unused_and_undeclared_features() {
+        let crate_directory = std::env::temp_dir().join(format!(
+            "unused-features-own-features-{}",
+            std::process::id()
+        ));
+        fs::create_dir_all(crate_directory.join("src")).unwrap();
+        fs::write(
+            crate_directory.join("src").join("lib.rs"),
+            r#"
+                #[cfg(feature = "net")]
+                mod net;
+                #[cfg(feature = "ghost")]
+                mod ghost;
+            "#,
+        )
+        .unwrap();
+        // Nested packages have their own features.
+        fs::create_dir_all(crate_directory.join("member").join("src")).unwrap();
+        fs::write(crate_directory.join("member").join("Cargo.toml"), "").unwrap();
+        fs::write(
+            crate_directory.join("member").join("src").join("lib.rs"),
+            r#"#[cfg(feature = "member")] mod member;"#,
+        )
+        .unwrap();
+
+        let package: Package = serde_json::from_value(serde_json::json!({
+            "name": "api",
+            "version": "0.1.0",
+            "id": "api 0.1.0 (path+file:///api)",
+            "dependencies": [],
+            "features": {
+                "default": ["net"],
+                "full": ["net"],
+                "net": [],
+                "serde": ["dep:serde"],
+                "extra": [],
+            },
+            "targets": [],
+            "manifest_path": crate_directory.join("Cargo.toml"),
+        }))
+        .unwrap();
+
+        let own_features = analyze_own_features(&package, &crate_directory);
+        fs::remove_dir_all(&crate_directory).unwrap();
+        let own_features = own_features.unwrap();
+
+        assert_eq!(own_features.unused_features, ["extra"]);
+        assert_eq!(own_features.undeclared_features, ["ghost"]);
+    }
+}
@@ -0,0 +1,102 @@
+use std::{process::Command, str::FromStr};
+
+use cargo_platform::{Cfg, Platform};
+
+/// The platforms the project is compiled for during the analysis.
+/// Used to decide if a `[target.'...'.dependencies]` table is part of the build at all.
+pub struct CompilePlatforms {
+    /// The target triples and their `cfg` values as reported by rustc.
+    platforms: Vec<(String, Vec<Cfg>)>,
+    /// The host triple and its `cfg` values, build scripts and proc-macros are compiled for it.
+    host: (String, Vec<Cfg>),
+}
+
+impl CompilePlatforms {
+    /// Queries rustc for the given build targets, or the host if no targets are given.
+    pub fn new(build_targets: &[String]) -> anyhow::Result<Self> {
+        let mut platforms = Vec::new();
+
+        let host = (host_triple()?, target_cfg(None)?);
+
+        if build_targets.is_empty() {
+            platforms.push(host.clone());
+        } else {
+            for target in build_targets {
+                platforms.push((target.clone(), target_cfg(Some(target))?));
+            }
+        }
+
+        Ok(Self { platforms, host })
+    }
+
+    /// Returns if the given `target.'...'` key matches any of the compiled platforms.
+    pub fn matches(&self, target: &str) -> bool {
+        match Platform::from_str(target) {
+            Ok(platform) => self
+                .platforms
+                .iter()
+                .any(|(name, cfg)| platform.matches(name, cfg)),
+            Err(e) => {
+                log::warn!("Failed to parse target '{target}'. {e}");
+                false
+            }
+        }
+    }
+
+    /// Returns if the given `target.'...'` key matches the host platform.
+    pub fn matches_host(&self, target: &str) -> bool {
+        let (name, cfg) = &self.host;
+
+        match Platform::from_str(target) {
+            Ok(platform) => platform.matches(name, cfg),
+            Err(e) => {
+                log::warn!("Failed to parse target '{target}'. {e}");
+                false
+            }
+        }
+    }
+}
+
+fn rustc() -> Command {
+    Command::new(std::env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string()))
+}
+
+/// Returns the host triple reported by `rustc -vV`.
+fn host_triple() -> anyhow::Result<String> {
+    let output = rustc().arg("-vV").output()?;
+    let stdout = String::from_utf8(output.stdout)?;
+
+    stdout
+        .lines()
+        .find_map(|line| line.strip_prefix("host: "))
+        .map(|host| host.trim().to_string())
+        .ok_or_else(|| anyhow::anyhow!("Failed to determine the host target from rustc."))
+}
+
+/// Returns the `cfg` values reported by `rustc --print=cfg` for the given target.
+fn target_cfg(target: Option<&String>) -> anyhow::Result<Vec<Cfg>> {
+    let mut command = rustc();
+    command.arg("--print=cfg");
+
+    if let Some(target) = target {
+        command.args(["--target", target]);
+    }
+
+    let output = command.output()?;
+
+    if !output.status.success() {
+        return Err(anyhow::anyhow!(
+            "Failed to fetch the cfg values from rustc. {}",
+            String::from_utf8_lossy(&output.stderr)
+        ));
+    }
+
+    let stdout = String::from_utf8(output.stdout)?;
+
+    let mut cfg = Vec::new();
+    for line in stdout.lines() {
+        cfg.push(Cfg::from_str(line)?);
+    }
+
+    Ok(cfg)
+}
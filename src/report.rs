@@ -7,7 +7,14 @@ use std::{
 
 use serde::{Deserialize, Serialize};
 
-pub const REPORT_VERSION: u16 = 0;
+use crate::{
+    create_dependencies::DependencyKey,
+    dependency_kind::DependencyKind,
+    feature_graph::FeatureActivation,
+    strategy::{Search, Strategy},
+};
+
+pub const REPORT_VERSION: u16 = 1;
 
 /// Serializable Report.
 #[derive(Serialize, Deserialize, Default)]
@@ -17,6 +24,19 @@ pub struct Report {
     pub(crate) root_name: String,
     /// The crates in the workspace or just a single crate.
     pub(crate) workspace_crates: HashMap<String, WorkspaceCrate>,
+    /// The `[workspace.dependencies]` definitions of the workspace root, if analyzed.
+    pub(crate) workspace_dependencies: Option<WorkspaceCrate>,
+    /// The selected workspace members if only a part of the workspace was analyzed.
+    /// `None` if all members were analyzed.
+    pub(crate) selected_packages: Option<Vec<String>>,
+    /// The own features enabled while verifying the removals.
+    pub(crate) compile_features: CompileFeatures,
+    /// How the removals of the dependencies built on each other.
+    pub(crate) strategy: Strategy,
+    /// How the features of the dependencies were searched.
+    pub(crate) search: Search,
+    /// The dependency features enabled by several workspace members, empty without `--unified-features`.
+    pub(crate) unified_features: Vec<UnifiedFeature>,
 }
 
 impl Report {
@@ -24,19 +44,59 @@ impl Report {
         Report {
             root_name: root_name.to_string(),
             workspace_crates: Default::default(),
+            workspace_dependencies: None,
+            selected_packages: None,
+            compile_features: CompileFeatures::default(),
+            strategy: Strategy::default(),
+            search: Search::default(),
+            unified_features: Vec::new(),
             version: REPORT_VERSION,
         }
     }
 
     /// Adds a new workspace crate to the report.
     pub fn add_workspace_crate(&mut self, crate_name: String, workspace_crate: WorkspaceCrate) {
-        if workspace_crate.dependencies.is_empty() {
+        if workspace_crate.dependencies.is_empty() && workspace_crate.own_features.is_none() {
             return;
         }
 
         self.workspace_crates.insert(crate_name, workspace_crate);
     }
 
+    /// Marks the report as partial, only the given workspace members are analyzed.
+    pub fn set_selected_packages(&mut self, selected_packages: Vec<String>) {
+        self.selected_packages = Some(selected_packages);
+    }
+
+    /// Sets the own features enabled while verifying the removals.
+    pub fn set_compile_features(&mut self, compile_features: CompileFeatures) {
+        self.compile_features = compile_features;
+    }
+
+    /// Sets how the removals of the dependencies built on each other.
+    pub fn set_strategy(&mut self, strategy: Strategy) {
+        self.strategy = strategy;
+    }
+
+    /// Sets how the features of the dependencies were searched.
+    pub fn set_search(&mut self, search: Search) {
+        self.search = search;
+    }
+
+    /// Sets the dependency features enabled by several workspace members.
+    pub fn set_unified_features(&mut self, unified_features: Vec<UnifiedFeature>) {
+        self.unified_features = unified_features;
+    }
+
+    /// Sets the analyzed `[workspace.dependencies]` definitions of the workspace root.
+    pub fn set_workspace_dependencies(&mut self, workspace_dependencies: WorkspaceCrate) {
+        if workspace_dependencies.dependencies.is_empty() {
+            return;
+        }
+
+        self.workspace_dependencies = Some(workspace_dependencies);
+    }
+
     /// Deserializes a report from the the given json-file.
     pub fn from(path: &Path) -> anyhow::Result<Report> {
         let mut contents = String::new();
@@ -66,12 +126,47 @@ impl Report {
     }
 }
 
+/// The own features the crates are compiled with, as passed with `--features`, `--all-features` and `--no-default-features`.
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct CompileFeatures {
+    pub(crate) features: Vec<String>,
+    pub(crate) all_features: bool,
+    pub(crate) no_default_features: bool,
+}
+
+impl std::fmt::Display for CompileFeatures {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut flags = Vec::new();
+
+        if self.all_features {
+            flags.push("--all-features".to_string());
+        }
+        if self.no_default_features {
+            flags.push("--no-default-features".to_string());
+        }
+        if !self.features.is_empty() {
+            flags.push(format!("--features {}", self.features.join(",")));
+        }
+
+        if flags.is_empty() {
+            write!(f, "default features")
+        } else {
+            write!(f, "{}", flags.join(" "))
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Default)]
 pub struct WorkspaceCrate {
     /// Full path to the crate.
     pub(crate) full_path: String,
     /// The dependencies of the crate and a report per dependency.
+    /// Dependencies outside of `[dependencies]` are prefixed with their table name, e.g. `dev-dependencies.tokio`.
     pub(crate) dependencies: HashMap<String, ReportDependencyEntry>,
+    /// The own feature combinations each removal was verified against, empty without `--feature-powerset`.
+    pub(crate) feature_combinations: Vec<Vec<String>>,
+    /// The findings of the own features analysis, if enabled with `--own-features`.
+    pub(crate) own_features: Option<OwnFeatures>,
 }
 
 impl WorkspaceCrate {
@@ -79,33 +174,160 @@ impl WorkspaceCrate {
         WorkspaceCrate {
             full_path: full_path.to_string_lossy().to_string(),
             dependencies: Default::default(),
+            feature_combinations: Default::default(),
+            own_features: None,
         }
     }
 
+    /// Adds a permutated dependency to the crate.
+    /// Returns the added entry so that additional details can be recorded.
     pub fn add_permutated_dependency(
         &mut self,
-        dependency_name: String,
+        dependency: &DependencyKey,
         all_features: HashSet<String>,
         successfully_removed_features: HashSet<String>,
         unsuccessfully_removed_features: HashSet<String>,
+    ) -> &mut ReportDependencyEntry {
+        let entry = self.dependencies.entry(dependency.to_string()).or_default();
+
+        *entry = ReportDependencyEntry {
+            name: dependency.name.clone(),
+            kind: dependency.kind,
+            target: dependency.target.clone(),
+            original_features: all_features,
+            successfully_removed_features,
+            unsuccessfully_removed_features,
+            ..Default::default()
+        };
+
+        entry
+    }
+
+    /// Adds an inherited dependency that enables no features on top of the ones of the workspace.
+    pub fn add_inherited_dependency(
+        &mut self,
+        dependency: &DependencyKey,
+        workspace_features: HashSet<String>,
+        host: bool,
+    ) -> &mut ReportDependencyEntry {
+        let entry = self.dependencies.entry(dependency.to_string()).or_default();
+
+        *entry = ReportDependencyEntry {
+            name: dependency.name.clone(),
+            kind: dependency.kind,
+            target: dependency.target.clone(),
+            host,
+            workspace_features,
+            ..Default::default()
+        };
+
+        entry
+    }
+
+    /// Adds a dependency declared for a platform that is not compiled, its features could not be verified.
+    pub fn add_unverified_dependency(
+        &mut self,
+        dependency: &DependencyKey,
+        all_features: HashSet<String>,
+        host: bool,
     ) {
         self.dependencies.insert(
-            dependency_name,
+            dependency.to_string(),
             ReportDependencyEntry {
+                name: dependency.name.clone(),
+                kind: dependency.kind,
+                target: dependency.target.clone(),
+                unverified_for_target: true,
+                host,
                 original_features: all_features,
-                successfully_removed_features,
-                unsuccessfully_removed_features,
+                ..Default::default()
             },
         );
     }
 }
 
+/// A dependency feature enabled by several workspace members.
+/// Cargo unifies the features across the members, thus it only disappears from the build if all of them remove it.
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct UnifiedFeature {
+    /// The package name of the dependency.
+    pub(crate) package: String,
+    /// The feature of the dependency.
+    pub(crate) feature: String,
+    /// The dependency is compiled for the host, its features are resolved separately from the runtime ones.
+    pub(crate) host: bool,
+    /// The workspace members enabling the feature.
+    pub(crate) enabled_by: Vec<String>,
+    /// The workspace compiled with the feature removed from all members together, thus it disappears from the build.
+    pub(crate) removable: bool,
+    /// The members the feature is removable from on their own, without effect as another member still enables it.
+    pub(crate) cosmetic_removals: Vec<String>,
+}
+
+/// The own `[features]` of a crate cross-referenced with the `cfg(feature = ...)` references in its sources.
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct OwnFeatures {
+    /// Declared features that no code refers to and that do not activate anything in use.
+    pub(crate) unused_features: Vec<String>,
+    /// Features referenced by the sources that are not declared.
+    pub(crate) undeclared_features: Vec<String>,
+}
+
 #[derive(Serialize, Deserialize, Default)]
 pub struct ReportDependencyEntry {
+    /// The name of the dependency in the toml file.
+    pub(crate) name: String,
+    /// The package name of a renamed dependency `x = { package = "y" }`.
+    pub(crate) package: Option<String>,
+    /// The resolved version of the dependency.
+    pub(crate) version: Option<String>,
+    /// The toml table the dependency is declared in.
+    pub(crate) kind: DependencyKind,
+    /// The platform of the `[target.'...'.dependencies]` table the dependency is declared in, if any.
+    pub(crate) target: Option<String>,
+    /// The platform does not match the host or the configured targets, thus nothing was verified.
+    pub(crate) unverified_for_target: bool,
     /// The original explicitly or implicitly enabled features of the dependency.
     pub(crate) original_features: HashSet<String>,
     /// The features that were successfully removed.
     pub(crate) successfully_removed_features: HashSet<String>,
     /// The features that were unsuccessfully removed.
     pub(crate) unsuccessfully_removed_features: HashSet<String>,
+    /// The features that were removable, but failed to compile along with the removals of the other dependencies.
+    /// Those are not claimed removable, only jointly compiled removals are.
+    pub(crate) jointly_unremovable_features: HashSet<String>,
+    /// The project compiled with `default-features = false` and only the explicitly listed features.
+    /// The features only enabled through the defaults are then removed as a whole, without trying them one by one.
+    pub(crate) default_features_removable: bool,
+    /// The features left were synthesized bottom-up from the compiler errors, before removing those one by one.
+    pub(crate) synthesized: bool,
+    /// The compiles the ddmin search saved compared to removing the features one by one, negative if it took more.
+    /// `None` for the linear search.
+    pub(crate) compiles_saved: Option<i64>,
+    /// The features enabled by the `[workspace.dependencies]` definition of an inherited dependency.
+    /// Those are not permutated as they can only be removed from the workspace root.
+    pub(crate) workspace_features: HashSet<String>,
+    /// The workspace members inheriting a `[workspace.dependencies]` definition, the removals are verified against those.
+    pub(crate) inherited_by: Vec<String>,
+    /// The dependency is compiled for the host, as build-dependency or dependency of a proc-macro crate.
+    /// With resolver 2 its features are resolved separately from the same package used at runtime.
+    pub(crate) host: bool,
+    /// The dependency is a member of the same workspace, e.g. a `path` dependency.
+    pub(crate) internal: bool,
+    /// The workspace members depending on the crate of an internal dependency, the removals are verified against each of those.
+    pub(crate) internal_dependents: Vec<String>,
+    /// The own features of the crate that were enabled while verifying the removals.
+    /// Empty if the crate was compiled with its default features.
+    pub(crate) active_features: Vec<String>,
+    /// The own feature combinations that failed to compile without a feature, by feature.
+    pub(crate) failed_feature_combinations: HashMap<String, Vec<Vec<String>>>,
+    /// What each of the original features activates in the dependency, followed transitively.
+    pub(crate) feature_activations: HashMap<String, FeatureActivation>,
+}
+
+impl ReportDependencyEntry {
+    /// Returns the key identifying the dependency in the toml file.
+    pub fn key(&self) -> DependencyKey {
+        DependencyKey::new(self.kind, self.target.as_deref(), &self.name)
+    }
 }
@@ -0,0 +1,45 @@
+use serde::{Deserialize, Serialize};
+
+/// How the removals of the dependencies build on each other while analyzing a crate.
+#[derive(clap::ValueEnum, Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Strategy {
+    /// Each dependency is verified against the original toml file.
+    #[default]
+    Independent,
+    /// The removals of the verified dependencies stay applied while verifying the next ones.
+    Cumulative,
+}
+
+impl std::fmt::Display for Strategy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Strategy::Independent => write!(f, "independent"),
+            Strategy::Cumulative => write!(f, "cumulative"),
+        }
+    }
+}
+
+/// How the features of a dependency are searched for the ones that can be removed.
+#[derive(clap::ValueEnum, Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Search {
+    /// Remove the features one by one, umbrella features along with their children first.
+    #[default]
+    Linear,
+    /// Remove groups of features at once and split only the groups that fail to compile, like delta debugging.
+    Ddmin,
+    /// Start from `default-features = false` and add the features the compiler errors point to until it compiles.
+    /// The features added are then removed one by one.
+    Synthesis,
+}
+
+impl std::fmt::Display for Search {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Search::Linear => write!(f, "linear"),
+            Search::Ddmin => write!(f, "ddmin"),
+            Search::Synthesis => write!(f, "synthesis"),
+        }
+    }
+}
@@ -1,8 +1,17 @@
-use std::path::Path;
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    path::Path,
+};
 
 use crate::{
-    cargo_project::CargoProject, create_dependencies::CrateDependencies,
-    feature_buffer::DependencyFeaturePermutator, report::WorkspaceCrate, utils, Report,
+    cargo_project::CargoProject,
+    create_dependencies::{CrateDependencies, DependencyKey},
+    dependency_kind::DependencyKind,
+    feature_buffer::DependencyFeaturePermutator,
+    feature_synthesis::GatedItems,
+    report::{CompileFeatures, UnifiedFeature, WorkspaceCrate},
+    strategy::{Search, Strategy},
+    utils, Report,
 };
 use clap::Args;
 
@@ -16,6 +25,22 @@ pub struct AnalyzeCommand {
     #[clap(short = 'w', long = "workspace", value_parser)]
     pub workspace: Option<String>,
 
+    /// Path to the 'Cargo.toml' of the project or workspace, an alternative to '--workspace'.
+    #[clap(long = "manifest-path", value_parser, conflicts_with = "workspace")]
+    pub manifest_path: Option<String>,
+
+    /// Only analyze the given workspace member. Can be supplied multiple times.
+    #[clap(short = 'p', long = "package")]
+    pub packages: Vec<String>,
+
+    /// Exclude the given workspace member from the analysis. Can be supplied multiple times.
+    #[clap(long = "exclude")]
+    pub exclude: Vec<String>,
+
+    /// Only analyze the `default-members` of the workspace.
+    #[clap(long = "default-members", action)]
+    pub default_members: bool,
+
     /// The absolute report 'directory' path to which the report will be written.
     /// If not specified it will be written to the current executable directory.
     #[clap(short = 'r', long = "report-dir", value_parser)]
@@ -53,6 +78,49 @@ pub struct AnalyzeCommand {
     /// Build all example targets.
     #[clap(long = "examples", action)]
     pub build_examples: bool,
+
+    /// Verify every removal against the powerset of the own features of the crate.
+    /// A feature is only removable if every combination compiles.
+    #[clap(long = "feature-powerset", action)]
+    pub feature_powerset: bool,
+    /// The maximum number of own features combined by '--feature-powerset'.
+    #[clap(long = "depth", value_parser, requires = "feature-powerset")]
+    pub feature_depth: Option<usize>,
+
+    /// Also report the own features no code refers to, and the `cfg(feature = ...)` references to undeclared features.
+    #[clap(long = "own-features", action)]
+    pub own_features_analysis: bool,
+
+    /// Space or comma separated list of own features to enable while verifying the removals.
+    #[clap(short = 'F', long = "features", value_delimiter = ',')]
+    pub features: Vec<String>,
+    /// Enable all own features while verifying the removals.
+    #[clap(long = "all-features", action)]
+    pub all_features: bool,
+    /// Do not enable the `default` own feature while verifying the removals.
+    #[clap(long = "no-default-features", action)]
+    pub no_default_features: bool,
+
+    /// How the removals of the dependencies build on each other, 'independent' or 'cumulative'.
+    /// The removals of all dependencies of a crate are verified together at the end, either way.
+    #[clap(long = "strategy", value_enum, default_value_t = Strategy::Independent)]
+    pub strategy: Strategy,
+
+    /// How the features of a dependency are searched, 'linear', 'ddmin' or 'synthesis'.
+    /// The ddmin search removes groups of features per compile and only splits up the groups that fail.
+    /// The synthesis search adds the features the compiler errors point to, starting from `default-features = false`.
+    #[clap(long = "search", value_enum, default_value_t = Search::Linear)]
+    pub search: Search,
+
+    /// Also analyze the `[workspace.dependencies]` definitions of the workspace root.
+    /// Removals are verified by compiling every member inheriting the dependency.
+    #[clap(long = "workspace-dependencies", action)]
+    pub workspace_dependencies: bool,
+
+    /// Also test removing the dependency features enabled by several workspace members from all of them together.
+    /// Cargo unifies the features across the members, removing a feature from one member only is cosmetic.
+    #[clap(long = "unified-features", action)]
+    pub unified_features: bool,
 }
 
 impl AnalyzeCommand {
@@ -60,10 +128,13 @@ impl AnalyzeCommand {
         utils::initialize_logger(self.log_level.clone());
 
         let current_exe = std::env::current_dir()?;
-        let workspace_path = self
-            .workspace
-            .take()
-            .unwrap_or_else(|| current_exe.display().to_string());
+        let workspace_path = match self.manifest_path.take() {
+            Some(manifest_path) => manifest_directory(&manifest_path)?,
+            None => self
+                .workspace
+                .take()
+                .unwrap_or_else(|| current_exe.display().to_string()),
+        };
 
         log::info!("{}", workspace_path);
 
@@ -71,29 +142,64 @@ impl AnalyzeCommand {
 
         match CargoProject::new(crate_path, self.clone()) {
             Ok(root_toml) => {
-                let workspace_members = root_toml.workspace_members();
-                if !workspace_members.is_empty() {
+                if root_toml.is_workspace() {
                     log::debug!("Workspace detected, iterating over workspace crates...");
 
-                    let mut report = Report::new("Workspace");
+                    let workspace_members = root_toml.selected_workspace_members()?;
+                    if workspace_members.is_empty() {
+                        return Err(anyhow::anyhow!(
+                            "No workspace member is selected, check the '--package' and '--exclude' arguments."
+                        ));
+                    }
+
+                    let mut report = Report::new(&root_toml.root_name());
+                    report.set_compile_features(self.compile_features());
+                    report.set_strategy(self.strategy);
+                    report.set_search(self.search);
+
+                    if self.is_partial_selection() {
+                        report.set_selected_packages(
+                            workspace_members
+                                .iter()
+                                .map(|(member_name, _)| member_name.clone())
+                                .collect(),
+                        );
+                    }
+
+                    if self.workspace_dependencies {
+                        find_unused_workspace_dependency_features(root_toml, &mut report);
+                    }
 
-                    for member_path in workspace_members {
-                        log::debug!("Processing '{}' crate ...", member_path.display());
+                    for (member_name, member_path) in &workspace_members {
+                        log::debug!(
+                            "Processing '{}' crate at '{}' ...",
+                            member_name,
+                            member_path.display()
+                        );
 
-                        match CargoProject::new(&member_path, self.clone()) {
+                        match CargoProject::new(member_path, self.clone()) {
                             Ok(workspace_member) => {
                                 find_unused_crate_features(workspace_member, &mut report)
                             }
                             Err(e) => {
-                                log::error!(
-                                    "Failed to load '{}' crate. {e}",
-                                    member_path.display()
-                                );
+                                log::error!("Failed to load '{member_name}' crate. {e}");
                             }
                         }
                     }
+
+                    if self.unified_features {
+                        find_unified_workspace_features(
+                            crate_path,
+                            &workspace_members,
+                            self.clone(),
+                            &mut report,
+                        );
+                    }
                 } else {
                     let mut report = Report::new(&root_toml.crate_name());
+                    report.set_compile_features(self.compile_features());
+                    report.set_strategy(self.strategy);
+                    report.set_search(self.search);
                     find_unused_crate_features(root_toml, &mut report);
                 }
             }
@@ -109,6 +215,40 @@ impl AnalyzeCommand {
     }
 }
 
+impl AnalyzeCommand {
+    /// Returns if only a part of the workspace members is selected for the analysis.
+    pub fn is_partial_selection(&self) -> bool {
+        self.default_members || !self.packages.is_empty() || !self.exclude.is_empty()
+    }
+
+    /// Returns the own features enabled while verifying the removals.
+    pub fn compile_features(&self) -> CompileFeatures {
+        CompileFeatures {
+            features: self.features.clone(),
+            all_features: self.all_features,
+            no_default_features: self.no_default_features,
+        }
+    }
+}
+
+/// Returns the directory of the given 'Cargo.toml' path.
+fn manifest_directory(manifest_path: &str) -> anyhow::Result<String> {
+    let manifest_path = Path::new(manifest_path);
+
+    if manifest_path.file_name() != Some("Cargo.toml".as_ref()) {
+        return Err(anyhow::anyhow!(
+            "The manifest path must point to a 'Cargo.toml' file."
+        ));
+    }
+
+    let directory = manifest_path
+        .parent()
+        .filter(|directory| !directory.as_os_str().is_empty())
+        .unwrap_or_else(|| Path::new("."));
+
+    Ok(directory.display().to_string())
+}
+
 pub fn find_unused_crate_features(toml_crate: CargoProject, report: &mut Report) {
     if let Err(e) = find_unused_features(toml_crate, report) {
         log::error!("Error while looking for unused features. {e}");
@@ -118,36 +258,273 @@ pub fn find_unused_crate_features(toml_crate: CargoProject, report: &mut Report)
 pub fn find_unused_features(mut toml: CargoProject, report: &mut Report) -> anyhow::Result<()> {
     let crate_dependency = toml.gather_meta_data();
 
-    permutate_features(crate_dependency, &mut toml, report)?;
+    log::info!("{}", format!("|===== Crate '{}' =====|", toml.crate_name()));
+
+    // Analyzed before any permutation, the own features table is modified while permutating.
+    let own_features = toml.own_features()?;
+
+    let mut workspace_crate = permutate_features(crate_dependency, &mut toml)?;
+    workspace_crate.own_features = own_features;
+
+    report.add_workspace_crate(toml.crate_name(), workspace_crate);
+
+    report.flush(&toml.report_path())
+}
+
+pub fn find_unused_workspace_dependency_features(toml_root: CargoProject, report: &mut Report) {
+    if let Err(e) = find_unused_workspace_dependencies(toml_root, report) {
+        log::error!("Error while looking for unused workspace dependency features. {e}");
+    }
+}
+
+pub fn find_unused_workspace_dependencies(
+    mut toml: CargoProject,
+    report: &mut Report,
+) -> anyhow::Result<()> {
+    let crate_dependency = toml.gather_workspace_meta_data()?;
+
+    log::info!("|===== Workspace dependencies =====|");
+
+    let workspace_dependencies = permutate_features(crate_dependency, &mut toml)?;
+
+    report.set_workspace_dependencies(workspace_dependencies);
 
     report.flush(&toml.report_path())
 }
 
+pub fn find_unified_workspace_features(
+    workspace_path: &Path,
+    workspace_members: &[(String, Box<Path>)],
+    config: AnalyzeCommand,
+    report: &mut Report,
+) {
+    if let Err(e) = find_unified_features(workspace_path, workspace_members, config, report) {
+        log::error!("Error while looking for unified workspace features. {e}");
+    }
+}
+
+/// A dependency of a workspace member enabling a feature, along with all features it enables.
+type EnablingDependency = (usize, DependencyKey, HashSet<String>);
+
+pub fn find_unified_features(
+    workspace_path: &Path,
+    workspace_members: &[(String, Box<Path>)],
+    config: AnalyzeCommand,
+    report: &mut Report,
+) -> anyhow::Result<()> {
+    log::info!("|===== Unified workspace features =====|");
+
+    let root_toml = CargoProject::new(workspace_path, config.clone())?;
+    let member_names = workspace_members
+        .iter()
+        .map(|(member_name, _)| member_name.clone())
+        .collect::<Vec<String>>();
+
+    let mut members = Vec::new();
+    // The dependencies enabling each feature, by package name, host and feature.
+    let mut enabled_by: BTreeMap<(String, bool, String), Vec<EnablingDependency>> = BTreeMap::new();
+
+    for (member_name, member_path) in workspace_members {
+        let member = CargoProject::new(member_path, config.clone())?;
+        let crate_dependencies = member.gather_meta_data();
+
+        for (dependency_key, features) in &crate_dependencies.dependency_features {
+            if !matches!(
+                dependency_key.kind,
+                DependencyKind::Normal | DependencyKind::Development | DependencyKind::Build
+            ) {
+                continue;
+            }
+
+            let package = match crate_dependencies.packages.get(dependency_key) {
+                Some(package) => package.name.clone(),
+                None => continue,
+            };
+            // With resolver 1 the host and target features of a package are unified as well.
+            let host = crate_dependencies.separate_host_features
+                && crate_dependencies
+                    .host_dependencies
+                    .contains(dependency_key);
+
+            for feature in features {
+                enabled_by
+                    .entry((package.clone(), host, feature.clone()))
+                    .or_default()
+                    .push((members.len(), dependency_key.clone(), features.clone()));
+            }
+        }
+
+        members.push((member_name.clone(), member));
+    }
+
+    let mut unified_features = Vec::new();
+
+    for ((package, host, feature), dependencies) in enabled_by {
+        let mut enabling_members = dependencies
+            .iter()
+            .map(|(member, _, _)| members[*member].0.clone())
+            .collect::<Vec<String>>();
+        enabling_members.sort();
+        enabling_members.dedup();
+
+        if enabling_members.len() < 2 {
+            continue;
+        }
+
+        // As verified by the analysis of each member on its own.
+        let is_removable_from = |(member, dependency_key, _): &EnablingDependency| {
+            report
+                .workspace_crates
+                .get(&members[*member].0)
+                .and_then(|workspace_crate| {
+                    workspace_crate
+                        .dependencies
+                        .get(&dependency_key.to_string())
+                })
+                .is_some_and(|entry| entry.successfully_removed_features.contains(&feature))
+        };
+
+        let mut removable_from = dependencies
+            .iter()
+            .filter(|dependency| is_removable_from(dependency))
+            .map(|(member, _, _)| members[*member].0.clone())
+            .collect::<Vec<String>>();
+        removable_from.sort();
+        removable_from.dedup();
+
+        // A member requiring the feature keeps it in the build, thus it is only tried if every member can remove it.
+        let removable = dependencies.iter().all(is_removable_from) && {
+            log::info!(
+                "Remove '{feature}' feature flag of '{package}' from {} together.",
+                enabling_members.join(", ")
+            );
+            removable_together(
+                &root_toml,
+                &mut members,
+                &dependencies,
+                &feature,
+                &member_names,
+            )?
+        };
+
+        if !removable && !removable_from.is_empty() {
+            log::info!(
+                "Removing '{feature}' feature flag of '{package}' from {} is cosmetic, other members still enable it.",
+                removable_from.join(", ")
+            );
+        }
+
+        unified_features.push(UnifiedFeature {
+            package,
+            feature,
+            host,
+            enabled_by: enabling_members,
+            removable,
+            cosmetic_removals: if removable { vec![] } else { removable_from },
+        });
+    }
+
+    report.set_unified_features(unified_features);
+
+    report.flush(&root_toml.report_path())
+}
+
+/// Removes the feature from all given dependencies together and compiles the whole workspace.
+fn removable_together(
+    root_toml: &CargoProject,
+    members: &mut [(String, CargoProject)],
+    dependencies: &[EnablingDependency],
+    feature: &str,
+    member_names: &[String],
+) -> anyhow::Result<bool> {
+    for (member, dependency_key, features) in dependencies {
+        let features = features
+            .iter()
+            .filter(|enabled_feature| enabled_feature.as_str() != feature)
+            .cloned()
+            .collect();
+
+        members[*member]
+            .1
+            .replace_dependency_features(dependency_key, features)?;
+    }
+
+    for (member, _, _) in dependencies {
+        members[*member].1.flush()?;
+    }
+
+    // Tests and examples are built if any of the dependencies is a dev-dependency.
+    let kind = if dependencies
+        .iter()
+        .any(|(_, dependency_key, _)| dependency_key.kind == DependencyKind::Development)
+    {
+        DependencyKind::Development
+    } else {
+        DependencyKind::Normal
+    };
+
+    let compiled = root_toml.try_compile(kind, member_names, &[]);
+
+    for (member, _, _) in dependencies {
+        members[*member].1.reset_dependencies()?;
+        members[*member].1.flush()?;
+    }
+
+    if let Err(e) = &compiled {
+        log::debug!("Failed to compile the workspace without '{feature}'. error: {e}");
+    }
+
+    Ok(compiled.is_ok())
+}
+
 fn permutate_features(
     crate_deps: CrateDependencies,
     toml: &mut CargoProject,
-    final_report: &mut Report,
-) -> anyhow::Result<()> {
-    let total_features: f32 = crate_deps
+) -> anyhow::Result<WorkspaceCrate> {
+    let total_features: usize = crate_deps
         .dependency_features
-        .iter()
-        .map(|f| f.1.len() as f32)
+        .values()
+        .map(HashSet::len)
         .sum();
     let total_deps = crate_deps.dependency_features.len() as f32;
 
     let mut workspace_report = WorkspaceCrate::new(&toml.toml_path());
 
-    log::info!("{}", format!("|===== Crate '{}' =====|", toml.crate_name()));
+    let feature_combinations = verified_feature_combinations(toml)?;
+    if feature_combinations.len() > 1 {
+        log::info!(
+            "Verifying each removal against {} own feature combinations.",
+            feature_combinations.len()
+        );
+        workspace_report.feature_combinations = feature_combinations.clone();
+    }
+
+    // Each removal is compiled with each of the own feature combinations.
+    let total_compiles = total_features * feature_combinations.len();
+    log::info!("Start pruning feature flags. The process will recompile the project {total_compiles} times.");
+
+    let no_feature_combinations: [Vec<String>; 1] = [vec![]];
 
-    log::info!("Start pruning feature flags. The process will recompile the project {total_features} times.");
+    // The removals of each dependency along with how they were verified, those are verified together at the end.
+    let mut removals = Vec::new();
 
-    for (i, (dependency_name, config)) in crate_deps
+    for (i, (dependency_key, config)) in crate_deps
         .dependency_features
         .iter()
         .filter(|f| !f.1.is_empty())
         .enumerate()
     {
-        let mut dependency_progress = 100.0 / total_deps * i as f32;
+        let host = crate_deps.host_dependencies.contains(dependency_key);
+
+        if let Some(target) = &dependency_key.target {
+            if !toml.is_target_compiled(target, host) {
+                log::info!("Skip dependency '{dependency_key}', its platform is not part of the build. Use '--target' to analyze it.");
+                workspace_report.add_unverified_dependency(dependency_key, config.clone(), host);
+                continue;
+            }
+        }
+
+        let dependency_progress = 100.0 / total_deps * i as f32;
         let next_dependency_progress = 100.0 / total_deps * (i as f32 + 1.0);
         let dependency_progress_str = format!("[{:.1}%]", dependency_progress);
 
@@ -156,37 +533,182 @@ fn permutate_features(
             format!(
                 "{}: ==== Dependency '{}', removing {} flags =====",
                 dependency_progress_str,
-                dependency_name,
+                dependency_key,
                 config.len()
             )
         );
 
-        let mut feature_buffer =
-            DependencyFeaturePermutator::new(Vec::from_iter(config.clone().into_iter()));
+        // Workspace dependency definitions are verified against the members that inherit them.
+        let inherited_by = crate_deps
+            .inherited_by
+            .get(dependency_key)
+            .map(Vec::as_slice)
+            .unwrap_or_default();
 
-        let progress_step =
-            (next_dependency_progress - dependency_progress) / feature_buffer.left_count() as f32;
+        // Internal dependencies are verified against the members depending on this crate as well.
+        // Each of those is compiled on its own, cargo would unify the features of the members compiled together.
+        let internal_dependents = crate_deps.internal_dependents.get(dependency_key);
+        let builds = match internal_dependents {
+            Some(internal_dependents) => std::iter::once(vec![])
+                .chain(
+                    internal_dependents
+                        .iter()
+                        .map(|dependent| vec![dependent.clone()]),
+                )
+                .collect::<Vec<Vec<String>>>(),
+            None => vec![inherited_by.to_vec()],
+        };
 
-        while !feature_buffer.features_left() {
-            let feature_progress_str = format!("[{:.1}%]", dependency_progress);
+        if let Some(internal_dependents) = internal_dependents.filter(|d| !d.is_empty()) {
+            log::info!(
+                "Verifying internal dependency '{dependency_key}' against this crate and each of {}.",
+                internal_dependents.join(", ")
+            );
+        }
 
-            let (permutated_features, removed_feature) = feature_buffer.permutated_features();
+        // Optional dependencies and references in the own `[features]` table only take effect with that own feature enabled.
+        let own_features = crate_deps
+            .active_features
+            .get(dependency_key)
+            .cloned()
+            .unwrap_or_default();
 
+        if !own_features.is_empty() {
             log::info!(
-                "{}",
-                format!(
-                    "{}: Prune '{}' feature flag from '{}'",
-                    feature_progress_str, removed_feature, dependency_name,
-                )
+                "Verifying '{dependency_key}' with features '{}' enabled.",
+                own_features.join(", ")
+            );
+        }
+
+        // The own features of the crate do not apply to the members inheriting a workspace dependency.
+        let feature_combinations = match dependency_key.kind {
+            DependencyKind::Workspace => &no_feature_combinations[..],
+            _ => &feature_combinations[..],
+        };
+        let mut failed_feature_combinations = HashMap::new();
+
+        // Tests and examples are built if a member inherits the dependency as dev-dependency.
+        let kind = if crate_deps
+            .inherited_as_dev_dependency
+            .contains(dependency_key)
+        {
+            DependencyKind::Development
+        } else {
+            dependency_key.kind
+        };
+
+        let verification = Verification {
+            kind,
+            builds,
+            features: own_features.clone(),
+            feature_combinations: feature_combinations.to_vec(),
+        };
+
+        let mut feature_buffer = DependencyFeaturePermutator::new(
+            Vec::from_iter(config.clone()),
+            crate_deps
+                .feature_activations
+                .get(dependency_key)
+                .unwrap_or(&HashMap::new()),
+            toml.search(),
+        );
+
+        // Bottom-up, only the features left after the synthesis are removed one by one.
+        let synthesized_features = match toml.search() {
+            Search::Synthesis => {
+                synthesize_features(toml, dependency_key, &crate_deps, config, &verification)?
+            }
+            _ => None,
+        };
+
+        if let Some(synthesized_features) = &synthesized_features {
+            feature_buffer
+                .remove_features(&config.difference(synthesized_features).cloned().collect());
+        }
+
+        // The cheapest win first, `default-features = false` with only the explicitly listed features.
+        let default_features_removable = match crate_deps.default_features.get(dependency_key) {
+            Some(default_features) if synthesized_features.is_none() => {
+                log::info!(
+                    "{}: Prune default features '{}' from '{}'",
+                    dependency_progress_str,
+                    default_features
+                        .iter()
+                        .cloned()
+                        .collect::<Vec<String>>()
+                        .join(", "),
+                    dependency_key
+                );
+
+                let explicit_features = config
+                    .difference(default_features)
+                    .cloned()
+                    .collect::<Vec<String>>();
+
+                let removable =
+                    try_features(toml, dependency_key, explicit_features, &verification)?;
+
+                if removable {
+                    log::debug!(
+                        "{}: Successfully compiled without default features.",
+                        dependency_progress_str
+                    );
+                    feature_buffer.remove_features(default_features);
+                }
+
+                removable
+            }
+            _ => false,
+        };
+
+        // The linear search compiles once per feature left.
+        let linear_compiles = feature_buffer.left_count() as i64;
+        let mut compiles = 0;
+        let mut feature_progress = dependency_progress;
+
+        while !feature_buffer.features_left() {
+            // The compiles done against the ones the search still needs, the groups split by ddmin add compiles.
+            feature_progress = feature_progress.max(
+                dependency_progress
+                    + (next_dependency_progress - dependency_progress) * compiles as f32
+                        / (compiles + feature_buffer.compiles_left()) as f32,
             );
+            let feature_progress_str = format!("[{:.1}%]", feature_progress);
 
-            if let Err(e) = toml.replace_dependency_features(dependency_name, permutated_features) {
+            let (permutated_features, removed_feature) = feature_buffer.permutated_features();
+
+            let removed_features = feature_buffer.removed_features().join(", ");
+            let children = &feature_buffer.removed_features()[1..];
+            if children.is_empty() {
+                log::info!(
+                    "{}",
+                    format!(
+                        "{}: Prune '{}' feature flag from '{}'",
+                        feature_progress_str, removed_feature, dependency_key,
+                    )
+                );
+            } else {
+                log::info!(
+                    "{}",
+                    format!(
+                        "{}: Prune '{}' feature flag along with '{}' from '{}'",
+                        feature_progress_str,
+                        removed_feature,
+                        children.join(", "),
+                        dependency_key,
+                    )
+                );
+            }
+
+            if let Err(e) = toml.replace_dependency_features(dependency_key, permutated_features) {
                 log::error!("Error while pruning feature flag. error: {e}");
+                feature_buffer.removal_failed();
                 continue; // skip this permutation
             }
 
             if let Err(e) = toml.flush() {
                 log::error!("Error while saving modified toml file. error: {e}");
+                feature_buffer.removal_failed();
                 continue; // skip this permutation
             }
 
@@ -196,36 +718,49 @@ fn permutate_features(
                 "Try compiling without feature flag."
             );
 
-            match toml.try_compile() {
-                Ok(_) => {
-                    feature_buffer
-                        .successfully_removed_features
-                        .insert(removed_feature.clone());
+            let failed_combinations = verification.failed_feature_combinations(toml);
+            compiles += 1;
 
-                    log::debug!(
-                        "{}: {}",
-                        feature_progress_str,
-                        "Successfully compiled without feature.flag."
-                    );
-                }
-                Err(e) => {
-                    feature_buffer
-                        .unsuccessfully_removed_features
-                        .insert(removed_feature.clone());
+            if failed_combinations.is_empty() {
+                feature_buffer.removal_succeeded();
 
+                log::debug!(
+                    "{}: {}",
+                    feature_progress_str,
+                    "Successfully compiled without feature.flag."
+                );
+            } else if feature_buffer.removal_failed() {
+                log::debug!(
+                    "{}: Failed to compile without '{}', permutating those separately.",
+                    feature_progress_str,
+                    removed_features
+                );
+            } else {
+                for (combination, e) in &failed_combinations {
                     log::debug!(
                         "{}",
                         format!(
-                            "{}: Failed to compile without feature flag. error: {}",
-                            feature_progress_str, e
+                            "{}: Failed to compile without feature flag with features [{}]. error: {}",
+                            feature_progress_str,
+                            combination.join(", "),
+                            e
                         )
                     );
                 }
+
+                // Only interesting if the feature looked removable under some of the combinations.
+                if failed_combinations.len() < feature_combinations.len() {
+                    failed_feature_combinations.insert(
+                        removed_feature.clone(),
+                        failed_combinations
+                            .into_iter()
+                            .map(|(combination, _)| combination)
+                            .collect::<Vec<Vec<String>>>(),
+                    );
+                }
             }
 
             toml.reset_dependencies()?;
-
-            dependency_progress += progress_step;
         }
 
         log::debug!(
@@ -234,17 +769,453 @@ fn permutate_features(
             toml.crate_name()
         );
 
+        let compiles_saved = match toml.search() {
+            Search::Linear | Search::Synthesis => None,
+            Search::Ddmin => {
+                let compiles_saved = linear_compiles - compiles as i64;
+                if compiles_saved >= 0 {
+                    log::info!(
+                        "{}: The ddmin search compiled {} times, {} times less than removing the features one by one.",
+                        dependency_progress_str,
+                        compiles,
+                        compiles_saved
+                    );
+                } else {
+                    log::info!(
+                        "{}: The ddmin search compiled {} times, {} extra compiles compared to removing the features one by one.",
+                        dependency_progress_str,
+                        compiles,
+                        -compiles_saved
+                    );
+                }
+                Some(compiles_saved)
+            }
+        };
+
         if !feature_buffer.successfully_removed_features.is_empty() {
-            workspace_report.add_permutated_dependency(
-                dependency_name.clone(),
+            let removal = Removal {
+                dependency_key: dependency_key.clone(),
+                features: config
+                    .difference(&feature_buffer.successfully_removed_features)
+                    .cloned()
+                    .collect(),
+                verification,
+            };
+
+            // Keep the removals applied while verifying the next dependencies.
+            if toml.strategy() == Strategy::Cumulative {
+                removal.apply(toml)?;
+                toml.keep_dependencies();
+            }
+
+            removals.push(removal);
+        }
+
+        let has_workspace_features = crate_deps
+            .workspace_features
+            .get(dependency_key)
+            .is_some_and(|workspace_features| !workspace_features.is_empty());
+
+        if !feature_buffer.successfully_removed_features.is_empty()
+            || !failed_feature_combinations.is_empty()
+            || has_workspace_features
+        {
+            let entry = workspace_report.add_permutated_dependency(
+                dependency_key,
                 feature_buffer.original_features,
                 feature_buffer.successfully_removed_features,
                 feature_buffer.unsuccessfully_removed_features,
             );
+
+            entry.workspace_features = crate_deps
+                .workspace_features
+                .get(dependency_key)
+                .cloned()
+                .unwrap_or_default();
+            entry.inherited_by = inherited_by.to_vec();
+            entry.host = host;
+            entry.internal = internal_dependents.is_some();
+            entry.internal_dependents = internal_dependents.cloned().unwrap_or_default();
+            entry.active_features = own_features;
+            entry.default_features_removable = default_features_removable;
+            entry.compiles_saved = compiles_saved;
+            entry.synthesized = synthesized_features.is_some();
+            entry.failed_feature_combinations = failed_feature_combinations;
+            entry.feature_activations = crate_deps
+                .feature_activations
+                .get(dependency_key)
+                .cloned()
+                .unwrap_or_default();
+            if let Some(package) = crate_deps.packages.get(dependency_key) {
+                if package.name != dependency_key.name {
+                    entry.package = Some(package.name.clone());
+                }
+                entry.version = Some(package.version.to_string());
+            }
+        }
+    }
+
+    // Inherited dependencies without features of their own still report the features enabled by the workspace.
+    for (dependency_key, workspace_features) in &crate_deps.workspace_features {
+        if !workspace_features.is_empty()
+            && !workspace_report
+                .dependencies
+                .contains_key(&dependency_key.to_string())
+        {
+            let entry = workspace_report.add_inherited_dependency(
+                dependency_key,
+                workspace_features.clone(),
+                crate_deps.host_dependencies.contains(dependency_key),
+            );
+
+            if let Some(package) = crate_deps.packages.get(dependency_key) {
+                if package.name != dependency_key.name {
+                    entry.package = Some(package.name.clone());
+                }
+                entry.version = Some(package.version.to_string());
+            }
+        }
+    }
+
+    // Only the removals that compiled together are claimed removable.
+    for dependency_key in verify_jointly(toml, removals)? {
+        if let Some(entry) = workspace_report
+            .dependencies
+            .get_mut(&dependency_key.to_string())
+        {
+            entry.jointly_unremovable_features =
+                std::mem::take(&mut entry.successfully_removed_features);
+            entry.default_features_removable = false;
+        }
+    }
+
+    Ok(workspace_report)
+}
+
+/// How the removals of a dependency were verified.
+#[derive(Clone, PartialEq)]
+struct Verification {
+    kind: DependencyKind,
+    /// The packages compiled by each build, the package of the toml file if empty.
+    builds: Vec<Vec<String>>,
+    features: Vec<String>,
+    feature_combinations: Vec<Vec<String>>,
+}
+
+impl Verification {
+    /// Returns if the crate compiles with each of the own feature combinations.
+    fn compiles(&self, toml: &CargoProject) -> bool {
+        let failed_combinations = self.failed_feature_combinations(toml);
+
+        for (combination, e) in &failed_combinations {
+            log::debug!(
+                "Failed to compile the removals together with features [{}]. error: {e}",
+                combination.join(", ")
+            );
+        }
+
+        failed_combinations.is_empty()
+    }
+
+    /// Compiles the crate with each of the own feature combinations enabled on top of the verified features,
+    /// followed by the other packages that are verified, each in a separate build.
+    /// Returns the combinations that failed to compile along with their error.
+    fn failed_feature_combinations(
+        &self,
+        toml: &CargoProject,
+    ) -> Vec<(Vec<String>, anyhow::Error)> {
+        if self.builds.iter().any(Vec::is_empty) {
+            let failed_combinations = self
+                .feature_combinations
+                .iter()
+                .filter_map(|combination| {
+                    let features = [&self.features[..], combination].concat();
+                    toml.try_compile(self.kind, &[], &features)
+                        .err()
+                        .map(|e| (combination.clone(), e))
+                })
+                .collect::<Vec<(Vec<String>, anyhow::Error)>>();
+
+            if !failed_combinations.is_empty() {
+                return failed_combinations;
+            }
+        }
+
+        // The own features only exist in the crate of the toml file, the other packages are compiled once with their own features.
+        for packages in self.builds.iter().filter(|packages| !packages.is_empty()) {
+            if let Err(e) = toml.try_compile(self.kind, packages, &[]) {
+                return self
+                    .feature_combinations
+                    .iter()
+                    .map(|combination| {
+                        (
+                            combination.clone(),
+                            anyhow::anyhow!("Failed to compile {}. {e}", packages.join(", ")),
+                        )
+                    })
+                    .collect();
+            }
+        }
+
+        vec![]
+    }
+
+    /// Returns the compiler output of the first build failing to compile, in the order `failed_feature_combinations` compiles those.
+    fn compiler_errors(&self, toml: &CargoProject) -> Option<String> {
+        let own_errors = self
+            .builds
+            .iter()
+            .any(Vec::is_empty)
+            .then(|| {
+                self.feature_combinations.iter().find_map(|combination| {
+                    toml.compiler_errors(
+                        self.kind,
+                        &[],
+                        &[&self.features[..], combination].concat(),
+                    )
+                })
+            })
+            .flatten();
+
+        own_errors.or_else(|| {
+            self.builds
+                .iter()
+                .filter(|packages| !packages.is_empty())
+                .find_map(|packages| toml.compiler_errors(self.kind, packages, &[]))
+        })
+    }
+}
+
+/// The features of a dependency left after its verified removals.
+struct Removal {
+    dependency_key: DependencyKey,
+    features: Vec<String>,
+    verification: Verification,
+}
+
+impl Removal {
+    /// Replaces the features of the dependency in the editable toml document, this does not update any files.
+    fn apply(&self, toml: &mut CargoProject) -> anyhow::Result<()> {
+        toml.replace_dependency_features(&self.dependency_key, self.features.clone())
+    }
+}
+
+/// Compiles the removals of all dependencies together, the way `prune` applies them.
+/// If that fails the removals are bisected until the largest set compiling together is found.
+/// Returns the dependencies whose removals failed to compile along with the others.
+fn verify_jointly(
+    toml: &mut CargoProject,
+    removals: Vec<Removal>,
+) -> anyhow::Result<Vec<DependencyKey>> {
+    // A single removal was already compiled as it is.
+    if removals.len() <= 1 {
+        return Ok(vec![]);
+    }
+
+    log::info!(
+        "Verifying the removals of {} dependencies together.",
+        removals.len()
+    );
+
+    toml.reset_original_dependencies();
+
+    let mut accepted = Vec::new();
+    let mut rejected = Vec::new();
+
+    bisect_removals(toml, &mut accepted, &mut rejected, removals)?;
+
+    toml.reset_original_dependencies();
+
+    for removal in &rejected {
+        log::info!(
+            "The removals of '{}' failed to compile along with the other removals.",
+            removal.dependency_key
+        );
+    }
+
+    Ok(rejected
+        .into_iter()
+        .map(|removal| removal.dependency_key)
+        .collect())
+}
+
+/// Accepts the given removals if those compile along with the accepted ones, otherwise the removals are split in halves.
+fn bisect_removals(
+    toml: &mut CargoProject,
+    accepted: &mut Vec<Removal>,
+    rejected: &mut Vec<Removal>,
+    mut removals: Vec<Removal>,
+) -> anyhow::Result<()> {
+    for removal in accepted.iter().chain(removals.iter()) {
+        removal.apply(toml)?;
+    }
+    toml.flush()?;
+
+    // Every removal is verified the way it was verified on its own, identical verifications are compiled once.
+    let mut verifications: Vec<&Verification> = Vec::new();
+    for removal in accepted.iter().chain(removals.iter()) {
+        if !verifications.contains(&&removal.verification) {
+            verifications.push(&removal.verification);
         }
     }
 
-    final_report.add_workspace_crate(toml.crate_name(), workspace_report);
+    let compiles = verifications
+        .iter()
+        .all(|verification| verification.compiles(toml));
+
+    toml.reset_dependencies()?;
+
+    if compiles {
+        accepted.append(&mut removals);
+    } else if removals.len() == 1 {
+        rejected.append(&mut removals);
+    } else {
+        let second_half = removals.split_off(removals.len() / 2);
+        bisect_removals(toml, accepted, rejected, removals)?;
+        bisect_removals(toml, accepted, rejected, second_half)?;
+    }
 
     Ok(())
 }
+
+/// Starts from `default-features = false, features = []` and adds the features the compiler errors point to until it compiles.
+/// Returns the features of the dependency enabled that way, followed transitively.
+/// Returns `None` if the compiler errors point to no feature left to add, the features are then removed top-down.
+fn synthesize_features(
+    toml: &mut CargoProject,
+    dependency_key: &DependencyKey,
+    crate_deps: &CrateDependencies,
+    config: &HashSet<String>,
+    verification: &Verification,
+) -> anyhow::Result<Option<HashSet<String>>> {
+    // The `dep/feature` references of the own features are not gated in the sources of the dependency.
+    if dependency_key.kind == DependencyKind::Feature {
+        return Ok(None);
+    }
+
+    let gated_items = match crate_deps.packages.get(dependency_key) {
+        Some(package) => GatedItems::new(package)?,
+        None => return Ok(None),
+    };
+    let activations = crate_deps.feature_activations.get(dependency_key);
+
+    let mut features = HashSet::new();
+
+    loop {
+        if let Err(e) =
+            toml.replace_dependency_features(dependency_key, Vec::from_iter(features.clone()))
+        {
+            log::error!("Error while synthesizing feature flags. error: {e}");
+            toml.reset_dependencies()?;
+            return Ok(None);
+        }
+        toml.flush()?;
+
+        let compiler_output = verification.compiler_errors(toml);
+
+        toml.reset_dependencies()?;
+
+        let compiler_output = match compiler_output {
+            Some(compiler_output) => compiler_output,
+            None => {
+                log::info!(
+                    "Synthesized features '{}' for '{dependency_key}'.",
+                    Vec::from_iter(features.clone()).join(", ")
+                );
+                return Ok(Some(features));
+            }
+        };
+
+        let added_features = gated_items
+            .features_for(&compiler_output)
+            .into_iter()
+            .filter(|feature| config.contains(feature) && !features.contains(feature))
+            .collect::<Vec<String>>();
+
+        if added_features.is_empty() {
+            log::info!("The compiler errors point to no feature of '{dependency_key}' left to add, removing its features top-down.");
+            log::debug!("{compiler_output}");
+            return Ok(None);
+        }
+
+        log::info!(
+            "Add '{}' feature flags to '{dependency_key}' as pointed to by the compiler errors.",
+            added_features.join(", ")
+        );
+
+        for feature in added_features {
+            if let Some(activation) = activations.and_then(|activations| activations.get(&feature))
+            {
+                features.extend(
+                    activation
+                        .features
+                        .iter()
+                        .filter(|child| config.contains(*child))
+                        .cloned(),
+                );
+            }
+            features.insert(feature);
+        }
+    }
+}
+
+/// Compiles the crate with the dependency features replaced by the given ones.
+/// Returns if it compiled with each of the own feature combinations.
+fn try_features(
+    toml: &mut CargoProject,
+    dependency_key: &DependencyKey,
+    features: Vec<String>,
+    verification: &Verification,
+) -> anyhow::Result<bool> {
+    if let Err(e) = toml.replace_dependency_features(dependency_key, features) {
+        log::error!("Error while pruning feature flags. error: {e}");
+        return Ok(false);
+    }
+
+    let compiled = match toml.flush() {
+        Ok(_) => verification.failed_feature_combinations(toml).is_empty(),
+        Err(e) => {
+            log::error!("Error while saving modified toml file. error: {e}");
+            false
+        }
+    };
+
+    toml.reset_dependencies()?;
+
+    Ok(compiled)
+}
+
+/// Returns the own feature combinations the removals are verified against.
+/// Combinations that fail to compile with the unmodified toml file are left out, those can not verify anything.
+/// Returns an error if none of the combinations compiles with the unmodified toml file.
+fn verified_feature_combinations(toml: &CargoProject) -> anyhow::Result<Vec<Vec<String>>> {
+    let feature_combinations = toml.feature_combinations();
+
+    if feature_combinations.len() <= 1 {
+        return Ok(feature_combinations);
+    }
+
+    let verified_combinations = feature_combinations
+        .into_iter()
+        .filter(
+            |combination| match toml.try_compile(DependencyKind::Normal, &[], combination) {
+                Ok(_) => true,
+                Err(e) => {
+                    log::warn!(
+                        "Skip feature combination [{}], it does not compile without removals. {e}",
+                        combination.join(", ")
+                    );
+                    false
+                }
+            },
+        )
+        .collect::<Vec<Vec<String>>>();
+
+    if verified_combinations.is_empty() {
+        return Err(anyhow::anyhow!(
+            "None of the own feature combinations compiles without removals, the removals can not be verified."
+        ));
+    }
+
+    Ok(verified_combinations)
+}
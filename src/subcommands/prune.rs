@@ -2,9 +2,8 @@ use std::{fs, path::Path};
 
 use crate::{utils, TomlEdit};
 use clap::Args;
-pub use clap::Parser;
 
-use crate::Report;
+use crate::{Report, WorkspaceCrate};
 
 /// Prunes the unused, but, enabled feature flags reported by `cargo unused-feature analyze`.
 #[derive(Args, Debug, Clone, Default)]
@@ -34,42 +33,70 @@ impl PruneCommand {
 
         log::info!("Loaded removal suggestions from {}.", self.input_json_path);
 
+        if let Some(selected_packages) = &report.selected_packages {
+            log::warn!(
+                "The report only covers part of the workspace, only pruning: {}.",
+                selected_packages.join(", ")
+            );
+        }
+
+        if let Some(workspace_dependencies) = report.workspace_dependencies {
+            log::info!("Start pruning features of the workspace dependencies.");
+            prune_workspace_crate(workspace_dependencies)?;
+        }
+
         for (crate_name, workspace_crate) in report.workspace_crates {
             log::info!("Start pruning features of crate {crate_name}.");
+            prune_workspace_crate(workspace_crate)?;
+        }
+        Ok(())
+    }
+}
 
-            let contents = fs::read_to_string(&Path::new(&workspace_crate.full_path))?;
-
-            let mut toml = TomlEdit::new(contents)?;
-
-            for (dep_name, dependency) in workspace_crate.dependencies {
-                let diff = dependency
-                    .original_features
-                    .difference(&dependency.successfully_removed_features);
-
-                log::info!("Start pruning features of dependency {dep_name}.");
-
-                match toml.replace_dependency_features(
-                    &dep_name,
-                    diff.cloned().into_iter().collect::<Vec<String>>(),
-                ) {
-                    Ok(_) => {}
-                    Err(e) => {
-                        log::error!(
-                            "Failed to remove features from dependency {}: {}",
-                            dep_name,
-                            e
-                        );
-                    }
-                }
-            }
+/// Prunes the reported features from the toml file of the given crate.
+fn prune_workspace_crate(workspace_crate: WorkspaceCrate) -> anyhow::Result<()> {
+    let contents = fs::read_to_string(Path::new(&workspace_crate.full_path))?;
 
-            let new_contents = toml.serialize()?;
-            fs::write(&workspace_crate.full_path, new_contents)?;
-            log::info!(
-                "Updated {} with pruned unused, but, enabled feature flags.",
-                workspace_crate.full_path
-            );
+    let mut toml = TomlEdit::new(contents)?;
+
+    for (dep_name, dependency) in workspace_crate.dependencies {
+        if dependency.unverified_for_target {
+            log::info!("Skip dependency {dep_name}, it was not verified for this target.");
+            continue;
+        }
+
+        // Entries can be reported without removals, e.g. for failed feature combinations or removals that failed jointly.
+        if dependency.successfully_removed_features.is_empty() {
+            log::debug!("Skip dependency {dep_name}, none of its features can be removed.");
+            continue;
+        }
+
+        let diff = dependency
+            .original_features
+            .difference(&dependency.successfully_removed_features);
+
+        log::info!("Start pruning features of dependency {dep_name}.");
+
+        match toml
+            .replace_dependency_features(&dependency.key(), diff.cloned().collect::<Vec<String>>())
+        {
+            Ok(_) => {}
+            Err(e) => {
+                log::error!(
+                    "Failed to remove features from dependency {}: {}",
+                    dep_name,
+                    e
+                );
+            }
         }
-        Ok(())
     }
+
+    let new_contents = toml.serialize()?;
+    fs::write(&workspace_crate.full_path, new_contents)?;
+    log::info!(
+        "Updated {} with pruned unused, but, enabled feature flags.",
+        workspace_crate.full_path
+    );
+
+    Ok(())
 }
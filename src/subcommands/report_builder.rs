@@ -5,7 +5,11 @@ use std::{fs, path::Path};
 
 use clap::Args;
 
-use crate::{utils, Report, ReportDependencyEntry};
+use crate::{
+    report::{CompileFeatures, OwnFeatures, UnifiedFeature},
+    strategy::{Search, Strategy},
+    utils, Report, ReportDependencyEntry,
+};
 
 /// Builds a simple HTML report from the output file of the `unused-features analyze` subcommand.
 #[derive(Args, Debug, Clone, Default)]
@@ -38,25 +42,68 @@ impl ReportBuildingCommand {
         let mut total_features = 0;
         let mut total_removed_features = 0;
         let mut total_crates = 0;
+        let mut total_compiles_saved = None;
 
         let mut body = String::new();
 
         log::info!("Start building HTML report.");
 
-        for (workspace_crate_name, workspace_crate) in report.workspace_crates {
+        body.push_str(&compile_features_note(
+            &report.compile_features,
+            report.strategy,
+            report.search,
+        ));
+
+        if let Some(selected_packages) = &report.selected_packages {
+            body.push_str(&partial_report_note(selected_packages));
+        }
+
+        let workspace_dependencies = report.workspace_dependencies.map(|workspace_dependencies| {
+            (
+                "[workspace.dependencies]".to_string(),
+                workspace_dependencies,
+            )
+        });
+
+        for (workspace_crate_name, workspace_crate) in workspace_dependencies
+            .into_iter()
+            .chain(report.workspace_crates)
+        {
             total_crates += 1;
 
             let mut dependencies_html_rows = String::new();
+            let mut internal_dependencies_html_rows = String::new();
 
             for (dependency_name, dependency) in workspace_crate.dependencies {
                 total_features += dependency.original_features.len();
                 total_removed_features += dependency.successfully_removed_features.len();
+                if let Some(compiles_saved) = dependency.compiles_saved {
+                    *total_compiles_saved.get_or_insert(0) += compiles_saved;
+                }
 
-                dependencies_html_rows
-                    .push_str(&dependency_html_table(dependency_name, dependency));
+                // Workspace-internal dependencies are listed separately from the external crates.
+                let rows = if dependency.internal {
+                    &mut internal_dependencies_html_rows
+                } else {
+                    &mut dependencies_html_rows
+                };
+
+                rows.push_str(&dependency_html_table(dependency_name, dependency));
             }
 
-            let html_table = dependencies_table(dependencies_html_rows);
+            let mut html_table = if internal_dependencies_html_rows.is_empty() {
+                dependencies_table(dependencies_html_rows)
+            } else {
+                format!(
+                    "<h4>Internal dependencies</h4>{}<h4>External dependencies</h4>{}",
+                    dependencies_table(internal_dependencies_html_rows),
+                    dependencies_table(dependencies_html_rows)
+                )
+            };
+
+            if let Some(own_features) = &workspace_crate.own_features {
+                html_table.push_str(&own_features_note(own_features));
+            }
             body.push_str(&collapsable_header(
                 html_table,
                 workspace_crate_name,
@@ -64,6 +111,14 @@ impl ReportBuildingCommand {
             ));
         }
 
+        if !report.unified_features.is_empty() {
+            body.push_str(&unified_features_table(&report.unified_features));
+        }
+
+        if let Some(total_compiles_saved) = total_compiles_saved {
+            body.push_str(&compiles_saved_note(total_compiles_saved));
+        }
+
         let totals_overview_html =
             totals_overview_table(total_crates, total_features, total_removed_features);
 
@@ -112,6 +167,102 @@ fn totals_overview_table(
     )
 }
 
+fn compile_features_note(
+    compile_features: &CompileFeatures,
+    strategy: Strategy,
+    search: Search,
+) -> String {
+    format!(
+        "
+    <p style='font-family: sans-serif'><b>Compiled with:</b> {}.<br/><b>Strategy:</b> {}, {} search.</p>
+       ",
+        compile_features, strategy, search
+    )
+}
+
+fn unified_features_table(unified_features: &[UnifiedFeature]) -> String {
+    let rows = unified_features
+        .iter()
+        .map(|unified_feature| {
+            let package = if unified_feature.host {
+                format!("{} (host)", unified_feature.package)
+            } else {
+                unified_feature.package.clone()
+            };
+
+            format!(
+                "
+        <tr>
+            <td>{}</td>
+            <td>{}</td>
+            <td>{}</td>
+            <td>{}</td>
+            <td>{}</td>
+        </tr>",
+                package,
+                unified_feature.feature,
+                unified_feature.enabled_by.join(", "),
+                if unified_feature.removable {
+                    "yes"
+                } else {
+                    "no"
+                },
+                unified_feature.cosmetic_removals.join(", ")
+            )
+        })
+        .collect::<String>();
+
+    format!(
+        "
+        <h3 style='font-family: sans-serif'>Features unified across the workspace</h3>
+        <table class=\"styled-table\">
+        <tr>
+            <th>Dependency</th>
+            <th>Feature</th>
+            <th>Enabled by</th>
+            <th>Removable from the build</th>
+            <th>Cosmetic removals</th>
+        </tr>
+        {}
+        </table>",
+        rows
+    )
+}
+
+fn compiles_saved_note(compiles_saved: i64) -> String {
+    let (title, compiles) = if compiles_saved >= 0 {
+        ("Compiles saved by the ddmin search", compiles_saved)
+    } else {
+        ("Extra compiles of the ddmin search", -compiles_saved)
+    };
+
+    format!(
+        "
+    <p style='font-family: sans-serif'><b>{}:</b> {}.</p>
+       ",
+        title, compiles
+    )
+}
+
+fn own_features_note(own_features: &OwnFeatures) -> String {
+    format!(
+        "
+    <p style='font-family: sans-serif'><b>Unused own features:</b> {}<br/><b>Undeclared features referenced by the code:</b> {}</p>
+       ",
+        own_features.unused_features.join(", "),
+        own_features.undeclared_features.join(", ")
+    )
+}
+
+fn partial_report_note(selected_packages: &[String]) -> String {
+    format!(
+        "
+    <p style='font-family: sans-serif'><b>Partial report:</b> only the following workspace members were analyzed: {}.</p>
+       ",
+        selected_packages.join(", ")
+    )
+}
+
 fn collapsable_header(table: String, crate_name: String, full_path: String) -> String {
     format!(
         " 
@@ -125,21 +276,114 @@ fn collapsable_header(table: String, crate_name: String, full_path: String) -> S
 }
 
 fn dependency_html_table(crate_name: String, dependency: ReportDependencyEntry) -> String {
+    let crate_name = match &dependency.package {
+        Some(package) => format!("{crate_name} ({package})"),
+        None => crate_name,
+    };
+    let crate_name = match &dependency.version {
+        Some(version) => format!("{crate_name} v{version}"),
+        None => crate_name,
+    };
+    let table = match &dependency.target {
+        Some(target) => format!("target.'{}'.{}", target, dependency.kind),
+        None => dependency.kind.to_string(),
+    };
+    let table = if dependency.host {
+        format!("{table} (host)")
+    } else {
+        table
+    };
+    let table = if dependency.synthesized {
+        format!("{table} (synthesized)")
+    } else {
+        table
+    };
+    let table = if dependency.active_features.is_empty() {
+        table
+    } else {
+        format!(
+            "{table} (with features: {})",
+            dependency.active_features.join(", ")
+        )
+    };
+    // Removed umbrella features, like `full`, can be replaced by the children that are still required.
+    let removed_features = dependency
+        .successfully_removed_features
+        .iter()
+        .map(|feature| {
+            let mut required_children = dependency
+                .feature_activations
+                .get(feature)
+                .map(|activation| {
+                    activation
+                        .features
+                        .iter()
+                        .filter(|child| dependency.original_features.contains(*child))
+                        .filter(|child| !dependency.successfully_removed_features.contains(*child))
+                        .cloned()
+                        .collect::<Vec<String>>()
+                })
+                .unwrap_or_default();
+            required_children.sort();
+
+            if required_children.is_empty() {
+                feature.clone()
+            } else {
+                format!("{feature} (replace with: {})", required_children.join(", "))
+            }
+        })
+        .collect::<Vec<String>>()
+        .join(", ");
     let original_features = dependency
         .original_features
         .into_iter()
         .collect::<Vec<String>>()
         .join(", ");
-    let successfully_removed_features = dependency
-        .successfully_removed_features
+    let successfully_removed_features = if dependency.unverified_for_target {
+        "unverified for this target".to_string()
+    } else if dependency.default_features_removable {
+        format!("default-features removable: {removed_features}")
+    } else {
+        removed_features
+    };
+    let unsuccessfully_removed_features = dependency
+        .unsuccessfully_removed_features
         .into_iter()
+        .map(
+            |feature| match dependency.failed_feature_combinations.get(&feature) {
+                Some(combinations) => format!(
+                    "{feature} (fails with: {})",
+                    combinations
+                        .iter()
+                        .map(|combination| format!("[{}]", combination.join(", ")))
+                        .collect::<Vec<String>>()
+                        .join(" ")
+                ),
+                None => feature,
+            },
+        )
+        .chain(
+            dependency
+                .jointly_unremovable_features
+                .into_iter()
+                .map(|feature| format!("{feature} (fails with the other removals)")),
+        )
         .collect::<Vec<String>>()
         .join(", ");
-    let unsuccessfully_removed_features = dependency
-        .unsuccessfully_removed_features
+    let mut workspace_features = dependency
+        .workspace_features
         .into_iter()
         .collect::<Vec<String>>()
         .join(", ");
+    if !dependency.inherited_by.is_empty() {
+        workspace_features = format!("Inherited by: {}", dependency.inherited_by.join(", "));
+    }
+    if !dependency.internal_dependents.is_empty() {
+        workspace_features = format!(
+            "Verified with: {}",
+            dependency.internal_dependents.join(", ")
+        );
+    }
 
     let dependency_html = format!(
         "
@@ -148,11 +392,15 @@ fn dependency_html_table(crate_name: String, dependency: ReportDependencyEntry)
             <td>{}</td>
             <td>{}</td>
             <td>{}</td>
+            <td>{}</td>
+            <td>{}</td>
         </tr>",
         crate_name,
+        table,
         original_features,
         successfully_removed_features,
-        unsuccessfully_removed_features
+        unsuccessfully_removed_features,
+        workspace_features
     );
 
     dependency_html
@@ -164,9 +412,11 @@ fn dependencies_table(dependency_rows: String) -> String {
         <table class=\"styled-table\">
         <tr>
             <th>Dependency</th>
+            <th>Table</th>
             <th>Original</th>
             <th>Potential Removable</th>
             <th>Unremovable</th>
+            <th>Workspace</th>
         </tr>
         {}
         </table>       